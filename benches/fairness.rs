@@ -0,0 +1,81 @@
+//! Lock fairness under contention, run with `cargo bench --bench fairness`
+//!
+//! Every thread takes the lock in a loop for a fixed time and counts how
+//! often it got in. A fair lock gives all threads about the same share,
+//! an unfair one lets a few threads take the lock again and again
+
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use collection::sync::{
+    clh_lock::RawClhLock,
+    mutex::{Mutex, RawLock, RawMutex},
+    ticket_lock::RawTicketLock,
+};
+
+const RUN_TIME: Duration = Duration::from_millis(500);
+
+/// Acquisitions per thread
+fn run<R: RawLock + Sync>(threads: usize) -> Vec<u64> {
+    let mutex = Mutex::<u64, R>::with_raw(0);
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut count = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let mut x = mutex.lock().unwrap();
+                        *x = black_box(*x + 1);
+                        count += 1;
+                    }
+                    count
+                })
+            })
+            .collect();
+
+        thread::sleep(RUN_TIME);
+        stop.store(true, Ordering::Relaxed);
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+fn report(name: &str, threads: usize, counts: &[u64]) {
+    let total: u64 = counts.iter().sum();
+    let mean = total as f64 / counts.len() as f64;
+    let var = counts
+        .iter()
+        .map(|&c| (c as f64 - mean).powi(2))
+        .sum::<f64>()
+        / counts.len() as f64;
+    let min = *counts.iter().min().unwrap();
+    let max = *counts.iter().max().unwrap();
+
+    // min/max is 1 for a perfectly fair lock, cv (stddev / mean) is 0
+    println!(
+        "{:>8} {:>8} {:>12} {:>10.3} {:>10.3}",
+        name,
+        threads,
+        total,
+        min as f64 / max.max(1) as f64,
+        var.sqrt() / mean.max(1.0),
+    );
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>8} {:>12} {:>10} {:>10}",
+        "lock", "threads", "total", "min/max", "cv"
+    );
+
+    for threads in [2, 4, 8] {
+        report("mutex", threads, &run::<RawMutex>(threads));
+        report("ticket", threads, &run::<RawTicketLock>(threads));
+        report("clh", threads, &run::<RawClhLock>(threads));
+    }
+}
@@ -0,0 +1,94 @@
+//! Contended lock throughput, run with `cargo bench --bench mutex`
+//!
+//! Compares the sleeping `Mutex` against a pure spin lock like the one it
+//! replaced, and the fair `TicketLock` and `ClhLock`. Every thread
+//! increments a shared counter under the lock
+
+use std::{
+    cell::UnsafeCell,
+    hint::{self, black_box},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use collection::sync::{
+    clh_lock::RawClhLock,
+    mutex::{Mutex, RawLock, RawMutex},
+    ticket_lock::RawTicketLock,
+};
+
+/// Total increments, split over the threads
+const OPS: usize = 400_000;
+
+struct SpinLock<T> {
+    flag: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .flag
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.data.get() });
+        self.flag.store(false, Ordering::Release);
+        result
+    }
+}
+
+fn run(threads: usize, op: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..OPS / threads {
+                    op();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Counts to OPS under a `Mutex` locked with R
+fn run_mutex<R: RawLock + Sync>(threads: usize) -> Duration {
+    let mutex = Mutex::<u64, R>::with_raw(0);
+    let time = run(threads, || {
+        let mut x = mutex.lock().unwrap();
+        *x = black_box(*x + 1);
+    });
+    assert_eq!(*mutex.lock().unwrap(), (OPS / threads * threads) as u64);
+    time
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>12} {:>12}",
+        "threads", "spin", "mutex", "ticket", "clh"
+    );
+
+    for threads in [2, 8, 32] {
+        let spin = SpinLock {
+            flag: AtomicBool::new(false),
+            data: UnsafeCell::new(0u64),
+        };
+        let spin_time = run(threads, || spin.with(|x| *x = black_box(*x + 1)));
+        assert_eq!(spin.with(|x| *x), (OPS / threads * threads) as u64);
+
+        println!(
+            "{:>8} {:>12?} {:>12?} {:>12?} {:>12?}",
+            threads,
+            spin_time,
+            run_mutex::<RawMutex>(threads),
+            run_mutex::<RawTicketLock>(threads),
+            run_mutex::<RawClhLock>(threads),
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    cell::Cell,
+    mem,
+    ptr::{self, null_mut},
+    rc::Rc,
+};
+
+use super::{
+    heap::{Compare, HeapId, MaxOrder, MinOrder, PriorityQueue},
+    vec::Vec,
+};
+
+struct Node<T> {
+    value: T,
+    slot: Option<Rc<Slot<T>>>,
+    parent: *mut Node<T>,
+    /// child with the highest degree, the others follow through sibling
+    child: *mut Node<T>,
+    /// next root in the root list, or next child of parent
+    sibling: *mut Node<T>,
+    degree: usize,
+}
+
+/// Shared between a node and its handles
+struct Slot<T> {
+    /// null once the element left the heap
+    node: Cell<*mut Node<T>>,
+    owner: Rc<HeapId>,
+}
+
+/// Refers to an element pushed with `BinomialHeap::push_with_handle`
+///
+/// Stays valid through melds, and is simply ignored once the element
+/// was popped or the heap dropped
+pub struct Handle<T> {
+    slot: Rc<Slot<T>>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+/// Binomial heap, O(log n) push, pop and meld
+pub struct BinomialHeap<T, C = MaxOrder> {
+    /// roots ordered by increasing degree
+    head: *mut Node<T>,
+    len: usize,
+    id: Rc<HeapId>,
+    cmp: C,
+}
+
+impl<T> BinomialHeap<T>
+where
+    T: PartialOrd,
+{
+    pub fn new() -> Self {
+        BinomialHeap::with_order(MaxOrder)
+    }
+}
+
+impl<T> BinomialHeap<T, MinOrder>
+where
+    T: PartialOrd,
+{
+    /// Creates a heap with the smallest element on top
+    pub fn new_min() -> Self {
+        BinomialHeap::with_order(MinOrder)
+    }
+}
+
+impl<T, C> BinomialHeap<T, C> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the node of handle if it is still in this heap
+    fn node(&self, handle: &Handle<T>) -> Option<*mut Node<T>> {
+        let node = handle.slot.node.get();
+        if node.is_null() || !HeapId::same(&handle.slot.owner, &self.id) {
+            None
+        } else {
+            Some(node)
+        }
+    }
+
+    /// Returns the element of handle, None if it is not in this heap
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.node(handle).map(|node| unsafe { &(*node).value })
+    }
+}
+
+impl<T, C> BinomialHeap<T, C>
+where
+    C: Compare<T>,
+{
+    pub fn with_order(cmp: C) -> Self {
+        BinomialHeap {
+            head: null_mut(),
+            len: 0,
+            id: HeapId::new(),
+            cmp,
+        }
+    }
+
+    /// Returns the element on top, O(log n)
+    pub fn peek(&self) -> Option<&T> {
+        let (_, top) = self.top();
+        if top.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*top).value) }
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.push_node(value, None);
+    }
+
+    /// Pushes value and returns a handle for `decrease_key`
+    pub fn push_with_handle(&mut self, value: T) -> Handle<T> {
+        let slot = Rc::new(Slot {
+            node: Cell::new(null_mut()),
+            owner: self.id.clone(),
+        });
+        let node = self.push_node(value, Some(slot.clone()));
+        slot.node.set(node);
+        Handle { slot }
+    }
+
+    fn push_node(&mut self, value: T, slot: Option<Rc<Slot<T>>>) -> *mut Node<T> {
+        let node = Box::into_raw(Box::new(Node {
+            value,
+            slot,
+            parent: null_mut(),
+            child: null_mut(),
+            sibling: null_mut(),
+            degree: 0,
+        }));
+        // counted before any comparison, a panicking one leaves the node
+        // in the root list
+        self.len += 1;
+        unsafe { self.union(node) };
+        node
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let (prev, top) = self.top();
+        if top.is_null() {
+            return None;
+        }
+
+        unsafe {
+            // unlink top from the root list
+            if prev.is_null() {
+                self.head = (*top).sibling;
+            } else {
+                (*prev).sibling = (*top).sibling;
+            }
+
+            // the children of top form a heap with decreasing degrees,
+            // reverse them into a root list
+            let mut children = null_mut();
+            let mut child = (*top).child;
+            while !child.is_null() {
+                let next = (*child).sibling;
+                (*child).parent = null_mut();
+                (*child).sibling = children;
+                children = child;
+                child = next;
+            }
+
+            let Node { value, slot, .. } = *Box::from_raw(top);
+            if let Some(slot) = slot {
+                slot.node.set(null_mut());
+            }
+
+            self.len -= 1;
+            self.union(children);
+            Some(value)
+        }
+    }
+
+    /// Moves all elements of other into self in O(log n)
+    pub fn meld(&mut self, mut other: Self) {
+        HeapId::forward(&other.id, &self.id);
+        let head = mem::replace(&mut other.head, null_mut());
+        self.len += mem::take(&mut other.len);
+        unsafe { self.union(head) };
+    }
+
+    /// Replaces the element of handle with value, which has to be at least
+    /// as close to the top (for `MinOrder` a smaller or equal one)
+    ///
+    /// Returns false and leaves the heap untouched if value would move the
+    /// element down, or if handle is not in this heap
+    pub fn decrease_key(&mut self, handle: &Handle<T>, value: T) -> bool {
+        let mut node = match self.node(handle) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        unsafe {
+            if !self.cmp.le(&(*node).value, &value) {
+                return false;
+            }
+            (*node).value = value;
+
+            // bubble up, the slots travel with their values
+            let mut parent = (*node).parent;
+            while !parent.is_null() && !self.cmp.le(&(*node).value, &(*parent).value) {
+                mem::swap(&mut (*node).value, &mut (*parent).value);
+                mem::swap(&mut (*node).slot, &mut (*parent).slot);
+                if let Some(slot) = &(*node).slot {
+                    slot.node.set(node);
+                }
+                if let Some(slot) = &(*parent).slot {
+                    slot.node.set(parent);
+                }
+
+                node = parent;
+                parent = (*node).parent;
+            }
+        }
+        true
+    }
+
+    /// Returns the root on top and the root before it
+    fn top(&self) -> (*mut Node<T>, *mut Node<T>) {
+        let mut prev = null_mut();
+        let mut top = self.head;
+        unsafe {
+            let mut cur_prev = self.head;
+            let mut cur = if top.is_null() {
+                null_mut()
+            } else {
+                (*top).sibling
+            };
+            while !cur.is_null() {
+                if !self.cmp.le(&(*cur).value, &(*top).value) {
+                    prev = cur_prev;
+                    top = cur;
+                }
+                cur_prev = cur;
+                cur = (*cur).sibling;
+            }
+        }
+        (prev, top)
+    }
+
+    /// Makes the root child the first child of the root parent,
+    /// both have the same degree
+    unsafe fn link(child: *mut Node<T>, parent: *mut Node<T>) {
+        (*child).parent = parent;
+        (*child).sibling = (*parent).child;
+        (*parent).child = child;
+        (*parent).degree += 1;
+    }
+
+    /// Merges two root lists by degree
+    unsafe fn merge_roots(mut a: *mut Node<T>, mut b: *mut Node<T>) -> *mut Node<T> {
+        let mut head = null_mut();
+        let mut tail: *mut Node<T> = null_mut();
+
+        while !a.is_null() || !b.is_null() {
+            let next = if b.is_null() || (!a.is_null() && (*a).degree <= (*b).degree) {
+                let next = a;
+                a = (*a).sibling;
+                next
+            } else {
+                let next = b;
+                b = (*b).sibling;
+                next
+            };
+
+            if tail.is_null() {
+                head = next;
+            } else {
+                (*tail).sibling = next;
+            }
+            tail = next;
+        }
+        head
+    }
+
+    /// Unites the root list other into the heap, linking trees of equal
+    /// degree
+    ///
+    /// Every comparison runs on a complete root list in `self.head`. If one
+    /// panics, some trees are left unlinked, but no node is lost
+    unsafe fn union(&mut self, other: *mut Node<T>) {
+        self.head = Self::merge_roots(self.head, other);
+        if self.head.is_null() {
+            return;
+        }
+
+        let mut prev: *mut Node<T> = null_mut();
+        let mut cur = self.head;
+        let mut next = (*cur).sibling;
+
+        while !next.is_null() {
+            let next_next = (*next).sibling;
+            if (*cur).degree != (*next).degree
+                || (!next_next.is_null() && (*next_next).degree == (*cur).degree)
+            {
+                // nothing to link, or three trees of the same degree in a
+                // row: move on and link the last two
+                prev = cur;
+                cur = next;
+            } else if self.cmp.le(&(*next).value, &(*cur).value) {
+                (*cur).sibling = next_next;
+                Self::link(next, cur);
+            } else {
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).sibling = next;
+                }
+                Self::link(cur, next);
+                cur = next;
+            }
+            next = (*cur).sibling;
+        }
+    }
+}
+
+impl<T, C> Drop for BinomialHeap<T, C> {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        if !self.head.is_null() {
+            stack.push(self.head);
+        }
+
+        while let Some(node) = stack.pop() {
+            let node = unsafe { Box::from_raw(node) };
+            if !node.child.is_null() {
+                stack.push(node.child);
+            }
+            if !node.sibling.is_null() {
+                stack.push(node.sibling);
+            }
+            if let Some(slot) = &node.slot {
+                slot.node.set(null_mut());
+            }
+        }
+        self.head = ptr::null_mut();
+    }
+}
+
+impl<T, C: Compare<T> + Default> Default for BinomialHeap<T, C> {
+    fn default() -> Self {
+        Self::with_order(C::default())
+    }
+}
+
+impl<T, C: Compare<T>> PriorityQueue<T> for BinomialHeap<T, C> {
+    fn push(&mut self, value: T) {
+        BinomialHeap::push(self, value)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        BinomialHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        BinomialHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        BinomialHeap::len(self)
+    }
+
+    fn meld(&mut self, other: Self) {
+        BinomialHeap::meld(self, other)
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for BinomialHeap<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for el in iter {
+            self.push(el);
+        }
+    }
+}
+
+impl<T, C: Compare<T> + Default> FromIterator<T> for BinomialHeap<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::default();
+        heap.extend(iter);
+        heap
+    }
+}
+
+#[test]
+fn binomial_heap_test_push_pop() {
+    let mut hp: BinomialHeap<i32> = [5, 3, 9, 1, 7, 3].into_iter().collect();
+    assert_eq!(hp.len(), 6);
+    assert_eq!(hp.peek(), Some(&9));
+
+    let mut other = BinomialHeap::new();
+    other.extend(10..15);
+    hp.meld(other);
+
+    let popped: std::vec::Vec<_> = std::iter::from_fn(|| hp.pop()).collect();
+    assert_eq!(popped, [14, 13, 12, 11, 10, 9, 7, 5, 3, 3, 1]);
+    assert_eq!(hp.pop(), None);
+
+    // exercise carries over many degrees
+    hp.extend((0..1000).map(|x| (x * 7919) % 1000));
+    for expected in (0..1000).rev() {
+        assert_eq!(hp.pop(), Some(expected));
+    }
+}
+
+#[test]
+fn binomial_heap_test_decrease_key() {
+    let mut hp = BinomialHeap::new_min();
+    let handles: std::vec::Vec<_> = (0..20).map(|x| hp.push_with_handle(x * 10)).collect();
+    assert_eq!(hp.pop(), Some(0));
+
+    // popped elements and moves in the wrong direction are refused
+    assert!(!hp.decrease_key(&handles[0], -1));
+    assert!(!hp.decrease_key(&handles[5], 60));
+
+    assert!(hp.decrease_key(&handles[15], 5));
+    assert!(hp.decrease_key(&handles[7], 1));
+    assert_eq!(hp.get(&handles[15]), Some(&5));
+    assert_eq!(hp.pop(), Some(1));
+    assert_eq!(hp.pop(), Some(5));
+    assert_eq!(hp.get(&handles[15]), None);
+
+    // handles survive melds, and are rejected by unrelated heaps
+    let mut other = BinomialHeap::new_min();
+    let h = other.push_with_handle(100);
+    assert!(!hp.decrease_key(&h, 0));
+    hp.meld(other);
+    assert!(hp.decrease_key(&h, 0));
+    assert!(hp.decrease_key(&handles[19], 3));
+    // the element moved around, the handle still follows it
+    assert!(hp.decrease_key(&handles[19], 2));
+    assert_eq!(hp.get(&handles[19]), Some(&2));
+
+    let popped: std::vec::Vec<_> = std::iter::from_fn(|| hp.pop()).collect();
+    assert_eq!(
+        popped,
+        [0, 2, 10, 20, 30, 40, 50, 60, 80, 90, 100, 110, 120, 130, 140, 160, 170, 180]
+    );
+}
+
+#[test]
+fn binomial_heap_test_drop() {
+    let counter = Rc::new(());
+    let mut hp = BinomialHeap::with_order(super::heap::KeyOrder(|el: &(i32, Rc<()>)| el.0));
+    for i in 0..50 {
+        hp.push((i, counter.clone()));
+    }
+    let handle = hp.push_with_handle((100, counter.clone()));
+    for _ in 0..10 {
+        hp.pop();
+    }
+    assert_eq!(Rc::strong_count(&counter), 42);
+    drop(hp);
+    assert_eq!(Rc::strong_count(&counter), 1);
+    drop(handle);
+}
+
+/// Runs op on a fresh heap, with a panic injected at every possible
+/// comparison in turn, until op goes through without panicking
+///
+/// After each run `len` must match what the heap holds, and every element
+/// created must be popped or dropped exactly once
+#[cfg(test)]
+fn binomial_heap_test_panic_injection(op: impl Fn(&mut BinomialHeap<super::heap::Bomb>)) {
+    use super::heap::{Bomb, BOMBS, FUSE};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    for fuse in 1.. {
+        BOMBS.with(|bombs| bombs.borrow_mut().clear());
+        let mut hp: BinomialHeap<Bomb> = (0..20).map(|i| Bomb::new((i * 7) % 13)).collect();
+
+        FUSE.with(|f| f.set(fuse));
+        let result = catch_unwind(AssertUnwindSafe(|| op(&mut hp)));
+        FUSE.with(|f| f.set(0));
+
+        // whatever order is left, the heap must stay usable
+        hp.push(Bomb::new(100));
+        let len = hp.len();
+        let mut popped = 0;
+        while hp.pop().is_some() {
+            popped += 1;
+        }
+        assert_eq!(popped, len, "fuse {fuse}");
+        drop(hp);
+        BOMBS.with(|bombs| assert!(bombs.borrow().iter().all(|&drops| drops == 1)));
+
+        if result.is_ok() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn binomial_heap_test_panic_safety() {
+    use super::heap::Bomb;
+
+    binomial_heap_test_panic_injection(|hp| {
+        for key in [3, 20, 0, 7, 11] {
+            hp.push(Bomb::new(key));
+        }
+    });
+    binomial_heap_test_panic_injection(|hp| {
+        for _ in 0..8 {
+            hp.pop();
+        }
+    });
+    binomial_heap_test_panic_injection(|hp| {
+        let other: BinomialHeap<Bomb> = (0..13).map(|i| Bomb::new(i % 5)).collect();
+        hp.meld(other);
+    });
+}
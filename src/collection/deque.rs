@@ -2,22 +2,23 @@
 
 use core::fmt;
 use std::{
+    cmp::Ordering,
     collections::VecDeque,
-    mem,
+    iter, mem,
     ops::{Range, RangeBounds},
     ptr, slice,
 };
 
 use super::{raw::raw_vec::RawVec, vec::Vec};
 
-struct Deque<T> {
+pub struct Deque<T> {
     head: usize,
     len: usize,
     buf: RawVec<T>,
 }
 
 /// return index for logical index
-fn wrap_idx(logical_idx: usize, cap: usize) -> usize {
+pub(crate) fn wrap_idx(logical_idx: usize, cap: usize) -> usize {
     assert!((logical_idx == 0 && cap == 0) || logical_idx < cap || (logical_idx - cap) < cap);
     if logical_idx >= cap {
         logical_idx - cap
@@ -35,6 +36,18 @@ impl<T> Deque<T> {
         }
     }
 
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            head: 0,
+            len: 0,
+            buf: RawVec::with_capacity(cap),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
     fn cap(&self) -> usize {
         self.buf.cap
     }
@@ -98,7 +111,7 @@ impl<T> Deque<T> {
         self.len == self.cap()
     }
 
-    fn is_empth(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.len == 0
     }
 
@@ -180,6 +193,28 @@ impl<T> Deque<T> {
         }
     }
 
+    /// Returns a reference to the element at logical index idx
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        if idx < self.len {
+            unsafe { Some(&*self.ptr().add(self.to_physical_idx(idx))) }
+        } else {
+            None
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+        self.head = 0;
+    }
+
     fn to_vec_physical(&self) -> Vec<T> {
         let mut vec = Vec::new();
         unsafe {
@@ -213,7 +248,7 @@ impl<T> Deque<T> {
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        if self.is_empth() {
+        if self.is_empty() {
             None
         } else {
             let old_head = self.head;
@@ -223,13 +258,196 @@ impl<T> Deque<T> {
         }
     }
     pub fn pop_back(&mut self) -> Option<T> {
-        if self.is_empth() {
+        if self.is_empty() {
             None
         } else {
             self.len -= 1;
             Some(unsafe { self.buffer_read(self.to_physical_idx(self.len)) })
         }
     }
+
+    /// Drops elements from the back until len is new_len
+    pub fn truncate(&mut self, new_len: usize) {
+        while self.len > new_len {
+            self.pop_back();
+        }
+    }
+
+    pub fn resize_with<F>(&mut self, new_len: usize, mut f: F)
+    where
+        F: FnMut() -> T,
+    {
+        if new_len > self.len {
+            while self.len < new_len {
+                self.push_back(f());
+            }
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    pub fn contains(&self, x: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let (a, b) = self.as_slice();
+        a.contains(x) || b.contains(x)
+    }
+
+    /// Rearranges the internal storage so that the elements are one
+    /// contiguous slice, and returns it
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        let cap = self.cap();
+        let len = self.len;
+
+        // free := free space in the buffer
+        // head_len := elements from head to the end of the buffer
+        // tail_len := elements wrapped around to the start of the buffer
+        let free = cap - len;
+        let head_len = cap - self.head;
+        let tail_len = len.saturating_sub(head_len);
+
+        if tail_len == 0 {
+            // already contiguous
+        } else if free >= head_len {
+            // enough room to shift the tail and then copy the head in front
+            //
+            //   [D E F G H . . . A B C ]
+            //   [. . . D E F G H A B C ]
+            //   [A B C D E F G H . . . ]
+            unsafe {
+                self.copy(0, head_len, tail_len);
+                self.copy_nooverlap(self.head, 0, head_len);
+            }
+            self.head = 0;
+        } else if free >= tail_len {
+            // enough room to shift the head and then copy the tail behind
+            //
+            //   [F G H . . . . A B C D E ]
+            //   [F G H A B C D E . . . . ]
+            //   [. . . A B C D E F G H . ]
+            unsafe {
+                self.copy(self.head, tail_len, head_len);
+                self.copy_nooverlap(0, len, tail_len);
+            }
+            self.head = tail_len;
+        } else if head_len > tail_len {
+            // free is smaller than both parts: put the parts right next to
+            // each other and rotate them into place
+            //
+            //   [H I J K . . A B C D E F G ]
+            //   [. . H I J K A B C D E F G ]
+            //   [. . A B C D E F G H I J K ]
+            unsafe {
+                if free != 0 {
+                    self.copy(0, free, tail_len);
+                }
+                // every slot in free..cap is initialized now
+                let slice = &mut *self.buffer_range(free..cap);
+                slice.rotate_left(tail_len);
+            }
+            self.head = free;
+        } else {
+            //   [F G H I J K . . A B C D E ]
+            //   [F G H I J K A B C D E . . ]
+            //   [A B C D E F G H I J K . . ]
+            unsafe {
+                if free != 0 {
+                    self.copy(self.head, tail_len, head_len);
+                }
+                // every slot in 0..len is initialized now
+                let slice = &mut *self.buffer_range(0..len);
+                slice.rotate_right(head_len);
+            }
+            self.head = 0;
+        }
+
+        unsafe { &mut *self.buffer_range(self.head..self.head + len) }
+    }
+
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.make_contiguous().sort();
+    }
+
+    pub fn sort_unstable(&mut self)
+    where
+        T: Ord,
+    {
+        self.make_contiguous().sort_unstable();
+    }
+
+    /// Binary searches a sorted deque with a comparator function
+    ///
+    /// Works directly on both halves of the ring buffer,
+    /// the deque does not have to be contiguous
+    pub fn binary_search_by<'a, F>(&'a self, mut f: F) -> Result<usize, usize>
+    where
+        F: FnMut(&'a T) -> Ordering,
+    {
+        let (front, back) = self.as_slice();
+
+        // compare against the first element of the wrapped part to
+        // decide which half to search in
+        match back.first().map(&mut f) {
+            Some(Ordering::Equal) => Ok(front.len()),
+            Some(Ordering::Less) => back
+                .binary_search_by(f)
+                .map(|idx| idx + front.len())
+                .map_err(|idx| idx + front.len()),
+            _ => front.binary_search_by(f),
+        }
+    }
+
+    pub fn binary_search(&self, x: &T) -> Result<usize, usize>
+    where
+        T: Ord,
+    {
+        self.binary_search_by(|el| el.cmp(x))
+    }
+
+    pub fn binary_search_by_key<'a, B, F>(&'a self, b: &B, mut f: F) -> Result<usize, usize>
+    where
+        F: FnMut(&'a T) -> B,
+        B: Ord,
+    {
+        self.binary_search_by(|el| f(el).cmp(b))
+    }
+
+    /// Returns the index of the first element for which pred is false,
+    /// assuming the deque is partitioned by pred
+    pub fn partition_point<P>(&self, mut pred: P) -> usize
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let (front, back) = self.as_slice();
+
+        if let Some(true) = back.first().map(&mut pred) {
+            back.partition_point(pred) + front.len()
+        } else {
+            front.partition_point(pred)
+        }
+    }
+}
+
+impl<T: Clone> Deque<T> {
+    pub fn resize(&mut self, new_len: usize, value: T) {
+        self.resize_with(new_len, || value.clone())
+    }
+}
+
+impl<T> Default for Deque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
 }
 
 impl<T: fmt::Debug> fmt::Debug for Deque<T> {
@@ -307,6 +525,159 @@ impl<'a, T> Iterator for Iter<'a, T> {
         (len, Some(len))
     }
 }
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.i2.next_back() {
+            Some(val) => Some(val),
+            None => {
+                mem::swap(&mut self.i1, &mut self.i2);
+                self.i2.next_back()
+            }
+        }
+    }
+}
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// What a `BoundedDeque` does when pushing into a full buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Evict the oldest element to make room for the new one
+    Overwrite,
+    /// Refuse the new element and hand it back to the caller
+    Reject,
+}
+
+/// Fixed capacity ring buffer on top of `Deque`
+///
+/// The buffer is allocated once in `new` and never grows,
+/// the ring arithmetic is the one of the underlying `Deque`
+pub struct BoundedDeque<T> {
+    inner: Deque<T>,
+    capacity: usize,
+    policy: Overflow,
+}
+
+impl<T> BoundedDeque<T> {
+    pub fn new(capacity: usize, policy: Overflow) -> Self {
+        assert!(capacity > 0, "BoundedDeque capacity must be non-zero");
+        Self {
+            inner: Deque::with_capacity(capacity),
+            capacity,
+            policy,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> Overflow {
+        self.policy
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.inner.len() == self.capacity
+    }
+
+    /// Pushes value to the back, following the overflow policy when full
+    ///
+    /// Returns `Err(value)` if the buffer is full and the policy is `Reject`.
+    /// Under `Overwrite` the evicted element is dropped,
+    /// use `push_back_overwrite` to get it back
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            match self.policy {
+                Overflow::Reject => return Err(value),
+                Overflow::Overwrite => {
+                    self.inner.pop_front();
+                }
+            }
+        }
+        self.inner.push_back(value);
+        Ok(())
+    }
+
+    /// Pushes value to the back, evicting the oldest element if full
+    ///
+    /// Ignores the overflow policy
+    pub fn push_back_overwrite(&mut self, value: T) -> Option<T> {
+        let evicted = if self.is_full() {
+            self.inner.pop_front()
+        } else {
+            None
+        };
+        self.inner.push_back(value);
+        evicted
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.inner.pop_front()
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.inner.pop_back()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.inner.get(idx)
+    }
+
+    /// Returns the oldest element
+    pub fn front(&self) -> Option<&T> {
+        self.inner.front()
+    }
+
+    /// Returns the newest element
+    pub fn back(&self) -> Option<&T> {
+        self.inner.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+
+    /// Iterates from the oldest to the newest element
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// Iterates over the (at most) n newest elements, newest first
+    ///
+    /// Walks the ring buffer in place, nothing is allocated
+    pub fn recent(&self, n: usize) -> iter::Take<iter::Rev<Iter<'_, T>>> {
+        self.inner.iter().rev().take(n)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BoundedDeque<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for BoundedDeque<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.inner)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BoundedDeque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
 
 #[test]
 fn test_deque_push_front() {
@@ -335,3 +706,173 @@ fn test_deque_push_back() {
         println!("{x}")
     }
 }
+
+#[test]
+fn test_deque_iter_rev() {
+    let mut dq = Deque::new();
+    for i in 0..6 {
+        dq.push_back(i);
+    }
+    for i in 0..3 {
+        dq.pop_front();
+        dq.push_back(6 + i);
+    }
+    // physical layout is now wrapped
+    let rev: std::vec::Vec<_> = dq.iter().rev().copied().collect();
+    assert_eq!(rev, [8, 7, 6, 5, 4, 3]);
+    assert_eq!(dq.iter().len(), 6);
+}
+
+#[test]
+fn test_bounded_deque_overwrite() {
+    let mut dq = BoundedDeque::new(3, Overflow::Overwrite);
+    assert_eq!(dq.push_back_overwrite(1), None);
+    assert_eq!(dq.push_back_overwrite(2), None);
+    assert_eq!(dq.push_back_overwrite(3), None);
+    assert!(dq.is_full());
+    assert_eq!(dq.push_back_overwrite(4), Some(1));
+    assert_eq!(dq.push_back(5), Ok(()));
+    assert_eq!(dq.len(), 3);
+    assert_eq!(dq.front(), Some(&3));
+    assert_eq!(dq.back(), Some(&5));
+
+    for i in 6..100 {
+        assert_eq!(dq.push_back_overwrite(i), Some(i - 3));
+    }
+    let all: std::vec::Vec<_> = dq.iter().copied().collect();
+    assert_eq!(all, [97, 98, 99]);
+}
+
+#[test]
+fn test_bounded_deque_reject() {
+    let mut dq = BoundedDeque::new(2, Overflow::Reject);
+    assert_eq!(dq.push_back("a"), Ok(()));
+    assert_eq!(dq.push_back("b"), Ok(()));
+    assert_eq!(dq.push_back("c"), Err("c"));
+    assert_eq!(dq.pop_front(), Some("a"));
+    assert_eq!(dq.push_back("c"), Ok(()));
+    let all: std::vec::Vec<_> = dq.into_iter().collect();
+    assert_eq!(all, ["b", "c"]);
+}
+
+#[test]
+fn test_bounded_deque_recent() {
+    let mut dq = BoundedDeque::new(5, Overflow::Overwrite);
+    for i in 0..12 {
+        dq.push_back_overwrite(i);
+    }
+    let window: i32 = dq.recent(3).sum();
+    assert_eq!(window, 11 + 10 + 9);
+    assert_eq!(dq.recent(10).count(), 5);
+    assert_eq!(dq.recent(2).max(), Some(&11));
+}
+
+/// Builds a deque of `0..len` (scaled by `step`) whose elements straddle
+/// the physical end of the buffer, with front_len elements before the wrap
+#[cfg(test)]
+fn wrapped_deque(cap: usize, len: usize, front_len: usize, step: i32) -> Deque<i32> {
+    let mut dq = Deque::with_capacity(cap);
+    // move head so that exactly front_len elements fit before the end
+    for _ in 0..(dq.cap() - front_len) {
+        dq.push_back(0);
+        dq.pop_front();
+    }
+    for i in 0..len as i32 {
+        dq.push_back(i * step);
+    }
+    assert_eq!(dq.cap(), cap);
+    dq
+}
+
+#[test]
+fn test_deque_make_contiguous() {
+    // (len, front_len) combinations for a buffer of 16, covering every branch:
+    // short head, short tail, no free space, and mostly full buffers
+    for &(len, front_len) in &[
+        (8, 3),
+        (8, 5),
+        (16, 4),
+        (16, 12),
+        (10, 7),
+        (14, 3),
+        (14, 11),
+        (5, 16),
+    ] {
+        let mut dq = wrapped_deque(16, len, front_len, 1);
+        let expected: std::vec::Vec<i32> = (0..len as i32).collect();
+        assert_eq!(dq.make_contiguous(), &expected[..]);
+        assert_eq!(dq.iter().copied().collect::<std::vec::Vec<_>>(), expected);
+        assert_eq!(dq.as_slice().1.len(), 0);
+
+        // still a working deque afterwards
+        dq.push_back(100);
+        dq.push_front(-1);
+        assert_eq!(dq.front(), Some(&-1));
+        assert_eq!(dq.back(), Some(&100));
+    }
+}
+
+#[test]
+fn test_deque_binary_search_wrapped() {
+    let dq = wrapped_deque(16, 12, 5, 2);
+    assert!(!dq.as_slice().1.is_empty());
+
+    for i in 0..12 {
+        assert_eq!(dq.binary_search(&(i * 2)), Ok(i as usize));
+        assert_eq!(dq.binary_search(&(i * 2 + 1)), Err(i as usize + 1));
+        assert_eq!(
+            dq.binary_search_by_key(&(i * 4), |&x| x * 2),
+            Ok(i as usize)
+        );
+    }
+    assert_eq!(dq.binary_search(&-1), Err(0));
+    assert_eq!(dq.partition_point(|&x| x < 9), 5);
+    assert_eq!(dq.partition_point(|&x| x < 100), 12);
+    assert_eq!(dq.partition_point(|&x| x < 0), 0);
+    assert!(dq.contains(&20));
+    assert!(!dq.contains(&21));
+}
+
+#[test]
+fn test_deque_sort_wrapped() {
+    let mut dq = Deque::with_capacity(8);
+    for i in [5, 3, 7, 1] {
+        dq.push_back(i);
+    }
+    for i in [4, 8, 2, 6] {
+        dq.push_front(i);
+    }
+    assert!(!dq.as_slice().1.is_empty());
+    dq.sort();
+    assert_eq!(dq.make_contiguous(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+    dq.push_front(9);
+    dq.push_front(0);
+    dq.sort_unstable();
+    assert_eq!(
+        dq.iter().copied().collect::<std::vec::Vec<_>>(),
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+    );
+}
+
+#[test]
+fn test_deque_resize() {
+    let mut dq = wrapped_deque(8, 6, 2, 1);
+    dq.resize(8, 7);
+    assert_eq!(
+        dq.iter().copied().collect::<std::vec::Vec<_>>(),
+        [0, 1, 2, 3, 4, 5, 7, 7]
+    );
+    dq.resize(3, 0);
+    assert_eq!(dq.iter().copied().collect::<std::vec::Vec<_>>(), [0, 1, 2]);
+
+    let mut n = 10;
+    dq.resize_with(5, || {
+        n += 1;
+        n
+    });
+    assert_eq!(
+        dq.iter().copied().collect::<std::vec::Vec<_>>(),
+        [0, 1, 2, 11, 12]
+    );
+}
@@ -121,7 +121,7 @@ where
             }
 
             self.len -= 1;
-            return Some(value);
+            Some(value)
         } else {
             while cur_node.next.is_some() {
                 let next = cur_node.next.as_mut().unwrap();
@@ -140,7 +140,7 @@ where
                     cur_node = cur_node.next.as_mut().unwrap();
                 }
             }
-            return None;
+            None
         }
     }
 
@@ -153,21 +153,31 @@ where
     }
 }
 
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Hash + std::cmp::PartialEq + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> fmt::Debug for HashMap<K, V>
 where
     K: fmt::Debug,
     V: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "HashMap entities:{}\n", &self.len).unwrap();
+        writeln!(f, "HashMap entities:{}", &self.len).unwrap();
 
         for (pos, node) in self.arr.iter().enumerate() {
             if node.is_none() {
-                write!(f, "[arr@{pos}]: No Exists\n").unwrap();
+                writeln!(f, "[arr@{pos}]: No Exists").unwrap();
             } else {
-                write!(f, "[arr@{pos}]:\n").unwrap();
+                writeln!(f, "[arr@{pos}]:").unwrap();
                 let node = node.as_ref().unwrap();
-                write!(f, "{:?}:{:?}\n", node.key, node.value).unwrap();
+                writeln!(f, "{:?}:{:?}", node.key, node.value).unwrap();
 
                 let mut padding = 1;
                 let mut cur_node = node.next.as_ref();
@@ -175,9 +185,9 @@ where
                     for _ in 0..padding {
                         write!(f, ">").unwrap();
                     }
-                    write!(
+                    writeln!(
                         f,
-                        "{:?}:{:?}\n",
+                        "{:?}:{:?}",
                         cur_node.unwrap().key,
                         cur_node.unwrap().value
                     )
@@ -1,52 +1,254 @@
 #![allow(unused)]
 use std::{
-    cmp,
+    cell::RefCell,
+    cmp::{self, Ordering},
     collections::BinaryHeap,
-    mem::{swap, ManuallyDrop},
+    mem::{self, swap, ManuallyDrop},
+    ops::{Deref, DerefMut},
     ptr,
+    rc::Rc,
+    slice, thread,
 };
 
-use super::vec::Vec;
+use super::vec::{_Drain, _IntoIter, Vec};
+
+/// Order used by `Heap`, the greatest element by this order is on top
+pub trait Compare<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+
+    /// Returns true if a <= b by this order
+    fn le(&self, a: &T, b: &T) -> bool {
+        self.compare(a, b) != Ordering::Greater
+    }
+}
+
+/// Greatest element on top, the default order of `Heap`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaxOrder;
+
+/// Smallest element on top
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinOrder;
+
+/// Element with the greatest key on top
+#[derive(Clone, Copy)]
+pub struct KeyOrder<F>(pub F);
+
+/// Order defined by a comparator closure, greatest on top
+#[derive(Clone, Copy)]
+pub struct FnOrder<F>(pub F);
+
+impl<T: PartialOrd + ?Sized> Compare<T> for MaxOrder {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.partial_cmp(b).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T: PartialOrd + ?Sized> Compare<T> for MinOrder {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.partial_cmp(a).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T: ?Sized, K: PartialOrd, F: Fn(&T) -> K> Compare<T> for KeyOrder<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a)
+            .partial_cmp(&(self.0)(b))
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Compare<T> for FnOrder<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a, b)
+    }
+}
+
+/// Common interface of the heaps in this crate
+pub trait PriorityQueue<T> {
+    fn push(&mut self, value: T);
+
+    /// Removes the element on top
+    fn pop(&mut self) -> Option<T>;
+
+    /// Returns the element on top
+    fn peek(&self) -> Option<&T>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Moves all elements of other into self
+    fn meld(&mut self, other: Self)
+    where
+        Self: Sized;
+}
+
+/// Identity of a heap handing out handles
+///
+/// When a heap is melded into another one its id forwards to the id of
+/// the other heap, so handles issued by either heap are recognized
+pub(crate) struct HeapId {
+    forward: RefCell<Option<Rc<HeapId>>>,
+}
+
+impl HeapId {
+    pub(crate) fn new() -> Rc<HeapId> {
+        Rc::new(HeapId {
+            forward: RefCell::new(None),
+        })
+    }
+
+    /// Returns the id at the end of the forwarding chain
+    pub(crate) fn find(id: &Rc<HeapId>) -> Rc<HeapId> {
+        let mut root = id.clone();
+        loop {
+            let next = root.forward.borrow().clone();
+            match next {
+                Some(next) => root = next,
+                None => break,
+            }
+        }
+
+        // compress the chain so later lookups are O(1)
+        let mut cur = id.clone();
+        while !Rc::ptr_eq(&cur, &root) {
+            let next = cur.forward.replace(Some(root.clone())).unwrap();
+            cur = next;
+        }
+        root
+    }
+
+    /// Forwards id (of a heap melded away) to into
+    pub(crate) fn forward(id: &Rc<HeapId>, into: &Rc<HeapId>) {
+        *id.forward.borrow_mut() = Some(into.clone());
+    }
+
+    /// Returns true if both ids lead to the same heap
+    pub(crate) fn same(a: &Rc<HeapId>, b: &Rc<HeapId>) -> bool {
+        Rc::ptr_eq(&HeapId::find(a), &HeapId::find(b))
+    }
+}
 
 #[derive(Debug)]
-struct Heap<T> {
+pub struct Heap<T, C = MaxOrder> {
     data: Vec<T>,
+    cmp: C,
+}
+
+impl<T, C> Heap<T, C> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len() == 0
+    }
+
+    /// Returns a reference to the greatest element
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    /// Iterates over the elements in arbitrary order
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns the underlying vector in arbitrary order
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    /// Removes all elements, yielding them in arbitrary order
+    pub fn drain(&mut self) -> _Drain<'_, T> {
+        self.data.drain()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
 }
 
 impl<T> Heap<T>
 where
     T: PartialOrd,
-    T: Copy,
 {
-    pub fn peek(&self) -> Option<T> {
-        if !self.is_empty() {
-            Some(self.data[0])
-        } else {
-            return None;
+    pub fn new() -> Self {
+        Heap::with_order(MaxOrder)
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        Heap {
+            data: Vec::with_capacity(cap),
+            cmp: MaxOrder,
         }
     }
 }
 
-impl<T> Heap<T>
+impl<T> Heap<T, MinOrder>
 where
     T: PartialOrd,
 {
-    pub fn new() -> Self {
-        Heap { data: Vec::new() }
+    /// Creates a heap with the smallest element on top
+    pub fn new_min() -> Self {
+        Heap::with_order(MinOrder)
     }
+}
 
-    pub fn with_capacity(cap: usize) -> Self {
+impl<T, F> Heap<T, FnOrder<F>>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    /// Creates a heap ordered by the comparator f, greatest on top
+    pub fn new_by(f: F) -> Self {
+        Heap::with_order(FnOrder(f))
+    }
+}
+
+impl<T, K, F> Heap<T, KeyOrder<F>>
+where
+    K: PartialOrd,
+    F: Fn(&T) -> K,
+{
+    /// Creates a heap ordered by the key f, greatest key on top
+    pub fn new_by_key(f: F) -> Self {
+        Heap::with_order(KeyOrder(f))
+    }
+}
+
+impl<T, C> Heap<T, C>
+where
+    C: Compare<T>,
+{
+    pub fn with_order(cmp: C) -> Self {
         Heap {
-            data: Vec::with_capacity(cap),
+            data: Vec::new(),
+            cmp,
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.data.len()
+    /// Heapifies data in O(n) using the order cmp
+    pub fn from_vec_with_order(data: Vec<T>, cmp: C) -> Self {
+        let mut heap = Heap { data, cmp };
+        heap.rebuild();
+        heap
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.data.len() == 0
+    /// Returns a mutable reference to the greatest element
+    ///
+    /// The heap is restored when the returned guard is dropped
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T, C>> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(PeekMut {
+                heap: self,
+                sift: false,
+            })
+        }
     }
 
     pub fn push(&mut self, value: T) {
@@ -67,13 +269,58 @@ where
         })
     }
 
+    /// Returns the elements in ascending order
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut end = self.len();
+        while end > 1 {
+            end -= 1;
+            // move the greatest element behind the heap part
+            self.data.swap(0, end);
+            self.sift_down_range(0, end);
+        }
+        self.into_vec()
+    }
+
+    /// Moves all elements of other into self, leaving other empty
+    pub fn append(&mut self, other: &mut Self) {
+        if self.len() < other.len() {
+            swap(self, other);
+        }
+
+        let start = self.len();
+        for el in other.data.drain() {
+            self.data.push(el);
+        }
+        self.rebuild_tail(start);
+    }
+
+    /// Removes all elements, yielding them in descending order
+    ///
+    /// Elements not consumed are removed when the iterator is dropped
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, C> {
+        DrainSorted { heap: self }
+    }
+
+    /// Keeps only the elements for which f returns true
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let old_len = self.len();
+        self.data.retain(f);
+        if self.len() != old_len {
+            self.rebuild();
+        }
+    }
+
     fn sift_up(&mut self, start: usize, idx: usize) -> usize {
+        let cmp = &self.cmp;
         let mut hole = unsafe { Hole::new(&mut self.data, idx) };
 
         while hole.idx() > start {
             let parent = (hole.idx - 1) / 2;
 
-            if hole.el() <= hole.get(parent) {
+            if cmp.le(hole.el(), hole.get(parent)) {
                 break;
             }
 
@@ -83,22 +330,30 @@ where
         hole.idx()
     }
 
-    fn sift_down(&mut self, mut idx: usize) {
+    fn sift_down(&mut self, idx: usize) {
         let end = self.data.len();
+        self.sift_down_range(idx, end);
+    }
+
+    /// sift down idx, only considering elements before end
+    fn sift_down_range(&mut self, mut idx: usize, end: usize) {
+        assert!(idx < end && end <= self.data.len());
         let start = idx;
 
+        let cmp = &self.cmp;
         let mut hole = unsafe { Hole::new(&mut self.data, idx) };
         let mut child = 2 * hole.idx() + 1;
 
         while child <= end.saturating_sub(2) {
-            child += (hole.get(child) <= hole.get(child + 1)) as usize;
+            child += cmp.le(hole.get(child), hole.get(child + 1)) as usize;
 
             hole.move_to(child);
 
             child = 2 * hole.idx() + 1;
         }
 
-        if child == end - 1 {
+        // a last child without a sibling
+        if child + 1 == end {
             hole.move_to(child);
         }
 
@@ -107,17 +362,198 @@ where
 
         self.sift_up(start, idx);
     }
+
+    /// Restores the heap property for the whole data in O(n)
+    fn rebuild(&mut self) {
+        let len = self.len();
+        let mut idx = len / 2;
+        while idx > 0 {
+            idx -= 1;
+            self.sift_down_range(idx, len);
+        }
+    }
+
+    /// Restores the heap property when data[start..] was pushed without sifting
+    fn rebuild_tail(&mut self, start: usize) {
+        let len = self.len();
+        if start == len {
+            return;
+        }
+        let tail_len = len - start;
+
+        // a rebuild costs about 2 * len comparisons,
+        // sifting up each tail element about tail_len * log2(start)
+        let better_to_rebuild = if start < tail_len {
+            true
+        } else {
+            let log2_start = (usize::BITS - start.leading_zeros() - 1) as usize;
+            2 * len < tail_len * log2_start
+        };
+
+        if better_to_rebuild {
+            self.rebuild();
+        } else {
+            for idx in start..len {
+                self.sift_up(0, idx);
+            }
+        }
+    }
+}
+
+impl<T, C: Compare<T> + Default> Default for Heap<T, C> {
+    fn default() -> Self {
+        Self::with_order(C::default())
+    }
+}
+
+impl<T: PartialOrd> From<Vec<T>> for Heap<T> {
+    /// Heapifies the vector in O(n)
+    fn from(data: Vec<T>) -> Self {
+        Heap::from_vec_with_order(data, MaxOrder)
+    }
+}
+
+impl<T, C: Compare<T> + Default> FromIterator<T> for Heap<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut data = Vec::new();
+        for el in iter {
+            data.push(el);
+        }
+        Heap::from_vec_with_order(data, C::default())
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for Heap<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let start = self.len();
+        for el in iter {
+            self.data.push(el);
+        }
+        self.rebuild_tail(start);
+    }
+}
+
+impl<T, C> IntoIterator for Heap<T, C> {
+    type Item = T;
+    type IntoIter = _IntoIter<T>;
+
+    /// Consumes the heap, yielding the elements in arbitrary order
+    fn into_iter(self) -> _IntoIter<T> {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a Heap<T, C> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T, C: Compare<T>> PriorityQueue<T> for Heap<T, C> {
+    fn push(&mut self, value: T) {
+        Heap::push(self, value)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        Heap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        Heap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        Heap::len(self)
+    }
+
+    /// Appends other, O(n + m) at worst
+    fn meld(&mut self, mut other: Self) {
+        self.append(&mut other)
+    }
+}
+
+/// Mutable access to the greatest element of a `Heap`
+///
+/// Sifts the element down on drop if it was accessed mutably
+pub struct PeekMut<'a, T: 'a, C: Compare<T> = MaxOrder> {
+    heap: &'a mut Heap<T, C>,
+    sift: bool,
+}
+
+impl<'a, T, C: Compare<T>> PeekMut<'a, T, C> {
+    /// Removes the peeked element from the heap
+    pub fn pop(mut this: PeekMut<'a, T, C>) -> T {
+        // pop restores the heap by itself
+        this.sift = false;
+        this.heap.pop().unwrap()
+    }
+}
+
+impl<T, C: Compare<T>> Deref for PeekMut<'_, T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.heap.data[0]
+    }
+}
+
+impl<T, C: Compare<T>> DerefMut for PeekMut<'_, T, C> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.sift = true;
+        &mut self.heap.data[0]
+    }
+}
+
+impl<T, C: Compare<T>> Drop for PeekMut<'_, T, C> {
+    fn drop(&mut self) {
+        if self.sift {
+            self.heap.sift_down(0);
+        }
+    }
+}
+
+/// Draining iterator of a `Heap` in descending order
+pub struct DrainSorted<'a, T, C: Compare<T> = MaxOrder> {
+    heap: &'a mut Heap<T, C>,
+}
+
+impl<T, C: Compare<T>> Iterator for DrainSorted<'_, T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.heap.len();
+        (len, Some(len))
+    }
+}
+
+impl<T, C: Compare<T>> Drop for DrainSorted<'_, T, C> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // a comparison may have panicked already, dropping the rest
+            // without comparing avoids a double panic
+            self.heap.clear();
+        } else {
+            for _ in &mut *self {}
+        }
+    }
 }
 
 /// Hole represent index without valid value
-struct Hole<'a, T: 'a> {
+pub(crate) struct Hole<'a, T: 'a> {
     data: &'a mut [T],
     el: ManuallyDrop<T>,
     idx: usize,
 }
 
 impl<'a, T> Hole<'a, T> {
-    fn new(data: &'a mut [T], idx: usize) -> Self {
+    pub(crate) fn new(data: &'a mut [T], idx: usize) -> Self {
         assert!(idx < data.len());
         let el = unsafe { ptr::read(data.get_unchecked(idx)) };
         Hole {
@@ -128,15 +564,15 @@ impl<'a, T> Hole<'a, T> {
     }
 
     /// Returns a refference to removed element
-    fn el(&self) -> &T {
+    pub(crate) fn el(&self) -> &T {
         &self.el
     }
 
-    fn idx(&self) -> usize {
+    pub(crate) fn idx(&self) -> usize {
         self.idx
     }
     /// Returns a refference to element at idx
-    fn get(&self, idx: usize) -> &T {
+    pub(crate) fn get(&self, idx: usize) -> &T {
         assert!(idx < self.data.len());
         unsafe { self.data.get_unchecked(idx) }
     }
@@ -144,7 +580,8 @@ impl<'a, T> Hole<'a, T> {
     /// move hole to new idx
     /// move given idx's data to hole's one
     /// and change hole's idx to given idx
-    fn move_to(&mut self, idx: usize) {
+    pub(crate) fn move_to(&mut self, idx: usize) {
+        assert!(idx < self.data.len() && idx != self.idx);
         unsafe {
             let ptr = self.data.as_mut_ptr();
             let idx_ptr = ptr.add(idx);
@@ -153,6 +590,12 @@ impl<'a, T> Hole<'a, T> {
         }
         self.idx = idx;
     }
+
+    /// swap the removed element with the element at idx
+    pub(crate) fn swap_with(&mut self, idx: usize) {
+        assert!(idx != self.idx);
+        swap(&mut *self.el, &mut self.data[idx]);
+    }
 }
 
 impl<T> Drop for Hole<'_, T> {
@@ -182,3 +625,341 @@ fn heap_test_new_push() {
         println!("{:?}", hp);
     }
 }
+
+#[cfg(test)]
+fn heap_test_collect<I: IntoIterator<Item = i32>>(iter: I) -> std::vec::Vec<i32> {
+    iter.into_iter().collect()
+}
+
+#[test]
+fn heap_test_peek() {
+    let mut hp = Heap::new();
+    assert_eq!(hp.peek(), None);
+    assert!(hp.peek_mut().is_none());
+
+    for s in ["b", "d", "a", "c"] {
+        hp.push(s.to_string());
+    }
+    assert_eq!(hp.peek().map(String::as_str), Some("d"));
+
+    // lowering the top element moves it down
+    *hp.peek_mut().unwrap() = "a".to_string();
+    assert_eq!(hp.peek().map(String::as_str), Some("c"));
+
+    // reading through the guard keeps the heap as is
+    assert_eq!(hp.peek_mut().unwrap().as_str(), "c");
+    assert_eq!(PeekMut::pop(hp.peek_mut().unwrap()), "c");
+    assert_eq!(hp.len(), 3);
+    assert_eq!(hp.pop().as_deref(), Some("b"));
+}
+
+#[test]
+fn heap_test_from_vec_sorted() {
+    let mut data = Vec::new();
+    for x in [9, 2, 7, 4, 4, 1, 8, 3, 6, 5, 0] {
+        data.push(x);
+    }
+    let hp = Heap::from(data);
+    assert_eq!(hp.peek(), Some(&9));
+    assert_eq!(&*hp.into_sorted_vec(), &[0, 1, 2, 3, 4, 4, 5, 6, 7, 8, 9]);
+
+    let hp: Heap<i32> = (0..100).rev().collect();
+    assert_eq!(
+        heap_test_collect(hp.into_sorted_vec()),
+        (0..100).collect::<std::vec::Vec<_>>()
+    );
+}
+
+#[test]
+fn heap_test_append_extend() {
+    let mut a: Heap<i32> = [1, 5, 3].into_iter().collect();
+    let mut b: Heap<i32> = (10..30).collect();
+    a.append(&mut b);
+    assert!(b.is_empty());
+    assert_eq!(a.len(), 23);
+
+    a.extend([100, -1]);
+    a.extend(40..42);
+    let mut expected: std::vec::Vec<i32> = [1, 5, 3, 100, -1, 40, 41].into();
+    expected.extend(10..30);
+    expected.sort_by(|x, y| y.cmp(x));
+    assert_eq!(heap_test_collect(a.drain_sorted()), expected);
+    assert!(a.is_empty());
+}
+
+#[test]
+fn heap_test_drain_retain() {
+    let mut hp: Heap<i32> = (0..20).collect();
+    hp.retain(|x| x % 3 == 0);
+    assert_eq!(hp.len(), 7);
+    assert_eq!(hp.peek(), Some(&18));
+
+    {
+        // dropping a partially consumed drain_sorted still empties the heap
+        let mut drain = hp.drain_sorted();
+        assert_eq!(drain.next(), Some(18));
+        assert_eq!(drain.next(), Some(15));
+    }
+    assert!(hp.is_empty());
+
+    hp.extend(0..10);
+    let mut all = heap_test_collect(hp.drain());
+    all.sort();
+    assert_eq!(all, (0..10).collect::<std::vec::Vec<_>>());
+    assert!(hp.is_empty());
+
+    hp.extend(0..10);
+    assert_eq!(hp.iter().count(), 10);
+    let mut all = heap_test_collect(hp);
+    all.sort();
+    assert_eq!(all, (0..10).collect::<std::vec::Vec<_>>());
+}
+
+#[test]
+fn heap_test_min_order() {
+    let mut hp = Heap::new_min();
+    for x in [5, 1, 8, 3, 9, 2] {
+        hp.push(x);
+    }
+    assert_eq!(hp.peek(), Some(&1));
+    assert_eq!(heap_test_collect(hp.drain_sorted()), [1, 2, 3, 5, 8, 9]);
+
+    let hp: Heap<i32, MinOrder> = (0..10).rev().collect();
+    // ascending by the heap order, which is descending by value here
+    assert_eq!(&*hp.into_sorted_vec(), &[9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn heap_test_by_key_and_fn() {
+    let mut by_len = Heap::new_by_key(|s: &&str| s.len());
+    for s in ["ccc", "a", "dddd", "bb"] {
+        by_len.push(s);
+    }
+    assert_eq!(by_len.pop(), Some("dddd"));
+    assert_eq!(by_len.pop(), Some("ccc"));
+
+    // (priority, insertion order) with the smallest priority on top
+    let mut tasks = Heap::new_by(|a: &(u32, u32), b: &(u32, u32)| b.cmp(a));
+    for task in [(3, 0), (1, 1), (2, 2), (1, 3)] {
+        tasks.push(task);
+    }
+    *tasks.peek_mut().unwrap() = (5, 4);
+    assert_eq!(
+        heap_test_collect(tasks.drain_sorted().map(|t| t.1 as i32)),
+        [3, 2, 0, 4]
+    );
+}
+
+#[test]
+fn heap_test_priority_queue_trait() {
+    use super::{binomial_heap::BinomialHeap, pairing_heap::PairingHeap};
+
+    fn check<Q: PriorityQueue<i32> + Default>() {
+        let mut a = Q::default();
+        let mut b = Q::default();
+        for x in [4, 9, 1, 7] {
+            a.push(x);
+        }
+        for x in [3, 8, 6] {
+            b.push(x);
+        }
+        a.meld(b);
+        assert_eq!(a.len(), 7);
+        assert_eq!(a.peek(), Some(&9));
+        let popped: std::vec::Vec<_> = std::iter::from_fn(|| a.pop()).collect();
+        assert_eq!(popped, [9, 8, 7, 6, 4, 3, 1]);
+        assert!(a.is_empty());
+    }
+
+    check::<Heap<i32>>();
+    check::<PairingHeap<i32>>();
+    check::<BinomialHeap<i32>>();
+}
+
+#[cfg(test)]
+thread_local! {
+    /// comparisons left before one panics, 0 disarms
+    pub(crate) static FUSE: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    /// number of `Bomb`s created so far, and how often each one was dropped
+    pub(crate) static BOMBS: RefCell<std::vec::Vec<usize>> = const { RefCell::new(std::vec::Vec::new()) };
+}
+
+/// Burns the fuse, panics when it runs out
+#[cfg(test)]
+fn heap_test_burn_fuse() {
+    let fire = FUSE.with(|fuse| match fuse.get() {
+        0 => false,
+        1 => {
+            fuse.set(0);
+            true
+        }
+        n => {
+            fuse.set(n - 1);
+            false
+        }
+    });
+    if fire {
+        panic!("injected panic");
+    }
+}
+
+/// Element whose comparisons burn the fuse, and which records its drops
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct Bomb {
+    pub(crate) id: usize,
+    pub(crate) key: u32,
+}
+
+#[cfg(test)]
+impl Bomb {
+    pub(crate) fn new(key: u32) -> Bomb {
+        let id = BOMBS.with(|bombs| {
+            let mut bombs = bombs.borrow_mut();
+            bombs.push(0);
+            bombs.len() - 1
+        });
+        Bomb { id, key }
+    }
+}
+
+#[cfg(test)]
+impl PartialEq for Bomb {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+impl PartialOrd for Bomb {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        heap_test_burn_fuse();
+        self.key.partial_cmp(&other.key)
+    }
+}
+
+#[cfg(test)]
+impl Drop for Bomb {
+    fn drop(&mut self) {
+        BOMBS.with(|bombs| bombs.borrow_mut()[self.id] += 1);
+    }
+}
+
+/// Runs op on a fresh heap, with a panic injected at every possible
+/// comparison in turn, until op goes through without panicking
+///
+/// After each run every element created must be either in the heap or
+/// dropped exactly once, and once the heap is gone dropped exactly once
+#[cfg(test)]
+fn heap_test_panic_injection(op: impl Fn(&mut Heap<Bomb>)) {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    for fuse in 1.. {
+        BOMBS.with(|bombs| bombs.borrow_mut().clear());
+        let mut heap: Heap<Bomb> = (0..20).map(|i| Bomb::new((i * 7) % 13)).collect();
+
+        FUSE.with(|f| f.set(fuse));
+        let result = catch_unwind(AssertUnwindSafe(|| op(&mut heap)));
+        FUSE.with(|f| f.set(0));
+
+        let mut in_heap = BOMBS.with(|bombs| vec![0; bombs.borrow().len()]);
+        for bomb in heap.iter() {
+            in_heap[bomb.id] += 1;
+        }
+        BOMBS.with(|bombs| {
+            for (id, drops) in bombs.borrow().iter().enumerate() {
+                assert_eq!(drops + in_heap[id], 1, "bomb {id} with fuse {fuse}");
+            }
+        });
+
+        // whatever order is left, the heap must stay usable
+        heap.push(Bomb::new(100));
+        while heap.pop().is_some() {}
+        drop(heap);
+        BOMBS.with(|bombs| assert!(bombs.borrow().iter().all(|&drops| drops == 1)));
+
+        if result.is_ok() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn heap_test_panic_safety() {
+    heap_test_panic_injection(|heap| {
+        for key in [3, 20, 0, 7] {
+            heap.push(Bomb::new(key));
+        }
+    });
+    heap_test_panic_injection(|heap| {
+        for _ in 0..8 {
+            heap.pop();
+        }
+    });
+    heap_test_panic_injection(|heap| {
+        heap.peek_mut().unwrap().key = 0;
+        heap.peek_mut().unwrap().key = 50;
+        PeekMut::pop(heap.peek_mut().unwrap());
+    });
+    heap_test_panic_injection(|heap| {
+        let mut other: Heap<Bomb> = Heap::new();
+        other.extend((0..30).map(|i| Bomb::new(i % 17)));
+        heap.append(&mut other);
+    });
+    heap_test_panic_injection(|heap| {
+        heap.retain(|bomb| {
+            heap_test_burn_fuse();
+            bomb.key % 2 == 0
+        });
+    });
+    heap_test_panic_injection(|heap| {
+        let mut drain = heap.drain_sorted();
+        drain.next();
+        drain.next();
+    });
+    heap_test_panic_injection(|heap| {
+        let sorted = mem::take(heap).into_sorted_vec();
+        *heap = Heap::from(sorted);
+    });
+}
+
+#[test]
+fn heap_test_nan() {
+    let values = [1.0, f64::NAN, 3.0, -2.0, f64::NAN, 0.5, f64::INFINITY];
+
+    let mut hp = Heap::new();
+    for v in values {
+        hp.push(v);
+    }
+    hp.extend(values);
+    hp.retain(|v| *v != 3.0);
+    assert_eq!(hp.len(), 12);
+
+    // order is meaningless with NaNs around, but nothing is lost or duplicated
+    let mut popped: std::vec::Vec<u64> =
+        std::iter::from_fn(|| hp.pop()).map(f64::to_bits).collect();
+    let mut expected: std::vec::Vec<u64> = values
+        .iter()
+        .chain(values.iter())
+        .filter(|v| **v != 3.0)
+        .map(|v| v.to_bits())
+        .collect();
+    popped.sort();
+    expected.sort();
+    assert_eq!(popped, expected);
+
+    let sorted = Heap::<f64>::from_iter(values).into_sorted_vec();
+    assert_eq!(sorted.len(), values.len());
+
+    // le agrees with compare, NaN is equal to everything
+    let key = KeyOrder(|v: &f64| *v);
+    for a in values {
+        for b in values {
+            let le = |o: Ordering| o != Ordering::Greater;
+            assert_eq!(MaxOrder.le(&a, &b), le(MaxOrder.compare(&a, &b)));
+            assert_eq!(MinOrder.le(&a, &b), le(MinOrder.compare(&a, &b)));
+            assert_eq!(key.le(&a, &b), le(key.compare(&a, &b)));
+        }
+    }
+    assert!(MaxOrder.le(&f64::NAN, &1.0) && MaxOrder.le(&1.0, &f64::NAN));
+}
@@ -0,0 +1,491 @@
+use std::mem::{self, swap};
+
+use super::{
+    heap::{Compare, Hole, MaxOrder, MinOrder},
+    vec::Vec,
+};
+
+/// Position of keys not in the heap
+const NONE: usize = usize::MAX;
+
+/// Key of an `IndexedHeap`, a small integer such as a node id
+///
+/// The index picks the key's slot in the position table
+pub trait HeapIndex: Copy {
+    fn index(self) -> usize;
+}
+
+macro_rules! heap_index {
+    ($($ty:ty),*) => {$(
+        impl HeapIndex for $ty {
+            fn index(self) -> usize {
+                usize::try_from(self).expect("key does not fit in usize")
+            }
+        }
+    )*};
+}
+
+heap_index!(u8, u16, u32, u64, usize);
+
+/// Priority queue of keys that can update the priority of queued keys
+///
+/// Keys index a dense position table, so they should be small integers
+/// such as node ids. Every operation is O(log n)
+pub struct IndexedHeap<K, P, C = MaxOrder> {
+    data: Vec<(K, P)>,
+    /// key -> position of the key in data
+    pos: Vec<usize>,
+    cmp: C,
+}
+
+impl<K, P> IndexedHeap<K, P>
+where
+    K: HeapIndex,
+    P: PartialOrd,
+{
+    pub fn new() -> Self {
+        IndexedHeap::with_order(MaxOrder)
+    }
+}
+
+impl<K, P> IndexedHeap<K, P, MinOrder>
+where
+    K: HeapIndex,
+    P: PartialOrd,
+{
+    /// Creates a heap with the smallest priority on top
+    pub fn new_min() -> Self {
+        IndexedHeap::with_order(MinOrder)
+    }
+}
+
+impl<K, P, C> IndexedHeap<K, P, C> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len() == 0
+    }
+
+    /// Returns the key with the top priority
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.data.first().map(|(key, priority)| (key, priority))
+    }
+
+    /// Iterates over keys and priorities in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> {
+        self.data.iter().map(|(key, priority)| (key, priority))
+    }
+}
+
+impl<K, P, C> IndexedHeap<K, P, C>
+where
+    K: HeapIndex,
+    C: Compare<P>,
+{
+    pub fn with_order(cmp: C) -> Self {
+        IndexedHeap {
+            data: Vec::new(),
+            pos: Vec::new(),
+            cmp,
+        }
+    }
+
+    fn position(&self, key: &K) -> Option<usize> {
+        let pos = *self.pos.get(key.index())?;
+        if pos == NONE {
+            None
+        } else {
+            Some(pos)
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn get_priority(&self, key: &K) -> Option<&P> {
+        self.position(key).map(|idx| &self.data[idx].1)
+    }
+
+    /// Pushes key with priority
+    ///
+    /// If key is already queued its priority is replaced,
+    /// and the old priority is returned
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        if self.contains(&key) {
+            return self.change_priority(&key, priority);
+        }
+
+        let key_idx = key.index();
+        while self.pos.len() <= key_idx {
+            self.pos.push(NONE);
+        }
+
+        let idx = self.data.len();
+        self.data.push((key, priority));
+        self.pos[key_idx] = idx;
+        self.sift_up(idx);
+        None
+    }
+
+    /// Removes the key with the top priority
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove_at(0))
+        }
+    }
+
+    /// Removes key from the heap, returning its priority
+    pub fn remove(&mut self, key: &K) -> Option<P> {
+        let idx = self.position(key)?;
+        Some(self.remove_at(idx).1)
+    }
+
+    /// Sets the priority of a queued key, returning the old one
+    pub fn change_priority(&mut self, key: &K, priority: P) -> Option<P> {
+        let idx = self.position(key)?;
+        let old = mem::replace(&mut self.data[idx].1, priority);
+        self.sift_update(idx);
+        Some(old)
+    }
+
+    /// Lowers the priority value of a queued key
+    ///
+    /// Does nothing and returns false if key is not queued or priority is
+    /// not less than the current one. With `MinOrder` this is the classic
+    /// decrease-key, moving the key towards the top
+    pub fn decrease_key(&mut self, key: &K, priority: P) -> bool
+    where
+        P: PartialOrd,
+    {
+        match self.position(key) {
+            Some(idx) if priority < self.data[idx].1 => {
+                self.data[idx].1 = priority;
+                self.sift_update(idx);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for (key, _) in self.data.drain() {
+            self.pos[key.index()] = NONE;
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) -> (K, P) {
+        let mut el = self.data.pop().unwrap();
+        let fill = idx < self.data.len();
+        if fill {
+            // fill the gap with the last element
+            swap(&mut el, &mut self.data[idx]);
+            self.pos[self.data[idx].0.index()] = idx;
+        }
+        // before sifting, a panicking comparison must not leave el queued
+        self.pos[el.0.index()] = NONE;
+        if fill {
+            self.sift_update(idx);
+        }
+        el
+    }
+
+    /// Moves the element at idx up or down after its priority changed
+    fn sift_update(&mut self, idx: usize) {
+        if self.sift_up(idx) == idx {
+            self.sift_down(idx);
+        }
+    }
+
+    fn sift_up(&mut self, idx: usize) -> usize {
+        let cmp = &self.cmp;
+        let mut hole = PosHole::new(&mut self.data, &mut self.pos, idx);
+
+        while hole.hole.idx() > 0 {
+            let parent = (hole.hole.idx() - 1) / 2;
+
+            if cmp.le(&hole.hole.el().1, &hole.hole.get(parent).1) {
+                break;
+            }
+
+            hole.move_to(parent);
+        }
+
+        hole.hole.idx()
+    }
+
+    fn sift_down(&mut self, idx: usize) {
+        let end = self.data.len();
+        let cmp = &self.cmp;
+        let mut hole = PosHole::new(&mut self.data, &mut self.pos, idx);
+        let mut child = 2 * idx + 1;
+
+        while child < end {
+            // pick the child with the higher priority
+            if child + 1 < end && !cmp.le(&hole.hole.get(child + 1).1, &hole.hole.get(child).1) {
+                child += 1;
+            }
+
+            if cmp.le(&hole.hole.get(child).1, &hole.hole.el().1) {
+                break;
+            }
+
+            hole.move_to(child);
+            child = 2 * hole.hole.idx() + 1;
+        }
+    }
+}
+
+/// `Hole` that keeps the position table up to date, also when a
+/// comparison panics halfway through a sift
+struct PosHole<'a, K: HeapIndex, P> {
+    hole: Hole<'a, (K, P)>,
+    pos: &'a mut Vec<usize>,
+}
+
+impl<'a, K: HeapIndex, P> PosHole<'a, K, P> {
+    fn new(data: &'a mut [(K, P)], pos: &'a mut Vec<usize>, idx: usize) -> Self {
+        PosHole {
+            hole: Hole::new(data, idx),
+            pos,
+        }
+    }
+
+    fn move_to(&mut self, idx: usize) {
+        let cur = self.hole.idx();
+        self.hole.move_to(idx);
+        self.pos[self.hole.get(cur).0.index()] = cur;
+    }
+}
+
+impl<K: HeapIndex, P> Drop for PosHole<'_, K, P> {
+    /// the element goes where the hole is, `Hole` moves it there right after
+    fn drop(&mut self) {
+        self.pos[self.hole.el().0.index()] = self.hole.idx();
+    }
+}
+
+impl<K, P, C> Default for IndexedHeap<K, P, C>
+where
+    K: HeapIndex,
+    C: Compare<P> + Default,
+{
+    fn default() -> Self {
+        Self::with_order(C::default())
+    }
+}
+
+#[cfg(test)]
+impl<K, P, C> IndexedHeap<K, P, C>
+where
+    K: HeapIndex,
+    C: Compare<P>,
+{
+    /// checks the heap property and the position table
+    fn check(&self) {
+        self.check_positions();
+        for (idx, (_, priority)) in self.data.iter().enumerate().skip(1) {
+            assert!(self.cmp.le(priority, &self.data[(idx - 1) / 2].1));
+        }
+    }
+
+    /// checks that the position table matches data
+    fn check_positions(&self) {
+        for (idx, (key, _)) in self.data.iter().enumerate() {
+            assert_eq!(self.pos[key.index()], idx);
+        }
+        let queued = self.pos.iter().filter(|&&pos| pos != NONE).count();
+        assert_eq!(queued, self.len());
+    }
+}
+
+#[test]
+fn indexed_heap_test_ops() {
+    let mut hp = IndexedHeap::new();
+    for key in 0..10usize {
+        assert_eq!(hp.push(key, key * 10), None);
+    }
+    hp.check();
+    assert_eq!(hp.peek(), Some((&9, &90)));
+    assert_eq!(hp.get_priority(&4), Some(&40));
+
+    assert_eq!(hp.change_priority(&2, 100), Some(20));
+    assert_eq!(hp.push(5, 5), Some(50));
+    assert!(hp.decrease_key(&9, 1));
+    assert!(!hp.decrease_key(&9, 1));
+    assert!(!hp.decrease_key(&42, 0));
+    hp.check();
+
+    assert_eq!(hp.remove(&7), Some(70));
+    assert_eq!(hp.remove(&7), None);
+    assert!(!hp.contains(&7));
+    hp.check();
+
+    let order: std::vec::Vec<_> = std::iter::from_fn(|| hp.pop()).collect();
+    assert_eq!(
+        order,
+        [
+            (2, 100),
+            (8, 80),
+            (6, 60),
+            (4, 40),
+            (3, 30),
+            (1, 10),
+            (5, 5),
+            (9, 1),
+            (0, 0)
+        ]
+    );
+    assert!(hp.is_empty());
+    hp.check();
+}
+
+#[test]
+fn indexed_heap_test_random_ops() {
+    let mut hp = IndexedHeap::new_min();
+    let mut reference: std::vec::Vec<Option<u64>> = vec![None; 64];
+
+    // xorshift, good enough to shuffle operations around
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut rand = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for _ in 0..5000 {
+        let key = (rand() % 64) as usize;
+        let priority = rand() % 1000;
+        match rand() % 4 {
+            0 => {
+                assert_eq!(hp.push(key, priority), reference[key]);
+                reference[key] = Some(priority);
+            }
+            1 => {
+                let decreased = reference[key].is_some_and(|old| priority < old);
+                assert_eq!(hp.decrease_key(&key, priority), decreased);
+                if decreased {
+                    reference[key] = Some(priority);
+                }
+            }
+            2 => assert_eq!(hp.remove(&key), reference[key].take()),
+            _ => {
+                let min = reference.iter().flatten().min().copied();
+                let popped = hp.pop();
+                assert_eq!(popped.map(|(_, p)| p), min);
+                if let Some((key, _)) = popped {
+                    reference[key] = None;
+                }
+            }
+        }
+        hp.check();
+    }
+}
+
+#[test]
+fn indexed_heap_test_dijkstra() {
+    // adjacency list: (to, weight)
+    let graph: [&[(usize, u32)]; 6] = [
+        &[(1, 7), (2, 9), (5, 14)],
+        &[(0, 7), (2, 10), (3, 15)],
+        &[(0, 9), (1, 10), (3, 11), (5, 2)],
+        &[(1, 15), (2, 11), (4, 6)],
+        &[(3, 6), (5, 9)],
+        &[(0, 14), (2, 2), (4, 9)],
+    ];
+
+    let mut dist = [u32::MAX; 6];
+    let mut queue = IndexedHeap::new_min();
+    dist[0] = 0;
+    queue.push(0usize, 0u32);
+
+    while let Some((node, d)) = queue.pop() {
+        for &(next, weight) in graph[node] {
+            let alt = d + weight;
+            if alt < dist[next] {
+                dist[next] = alt;
+                if !queue.decrease_key(&next, alt) {
+                    queue.push(next, alt);
+                }
+            }
+        }
+    }
+
+    assert_eq!(dist, [0, 7, 9, 20, 20, 11]);
+}
+
+#[test]
+fn indexed_heap_test_wide_keys() {
+    let mut hp = IndexedHeap::new_min();
+    for key in [7u32, 3, 11] {
+        hp.push(key, key);
+    }
+    assert!(hp.decrease_key(&11, 1));
+    assert_eq!(hp.pop(), Some((11, 1)));
+
+    let mut hp = IndexedHeap::new();
+    hp.push(5u64, 'a');
+    hp.push(2u64, 'z');
+    assert_eq!(hp.peek(), Some((&2, &'z')));
+    hp.check();
+}
+
+#[test]
+fn indexed_heap_test_panic_safety() {
+    use super::heap::FnOrder;
+    use std::{
+        cell::Cell,
+        panic::{catch_unwind, AssertUnwindSafe},
+    };
+
+    // comparisons left before one panics, 0 disarms
+    let fuse = Cell::new(0);
+    let order = FnOrder(|a: &u32, b: &u32| {
+        match fuse.get() {
+            0 => {}
+            1 => {
+                fuse.set(0);
+                panic!("injected panic");
+            }
+            n => fuse.set(n - 1),
+        }
+        a.cmp(b)
+    });
+
+    for shot in 1.. {
+        let mut hp = IndexedHeap::with_order(order);
+        for key in 0..16u32 {
+            hp.push(key, (key * 7) % 13);
+        }
+
+        fuse.set(shot);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            hp.change_priority(&3, 20);
+            hp.remove(&9);
+            hp.decrease_key(&12, 0);
+            hp.pop();
+            hp.push(40, 5);
+        }));
+        fuse.set(0);
+
+        // the order may be off after a panic, the positions may not
+        hp.check_positions();
+        let len = hp.len();
+        let mut popped = 0;
+        while let Some((key, _)) = hp.pop() {
+            assert!(!hp.contains(&key));
+            hp.check_positions();
+            popped += 1;
+        }
+        assert_eq!(popped, len);
+
+        if result.is_ok() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::mem::{replace, swap};
+
+use super::{heap::Hole, vec::Vec};
+
+/// Double ended priority queue
+///
+/// Nodes on even levels (the root is level 0) are smaller than all of
+/// their descendants, nodes on odd levels are greater. The minimum is the
+/// root and the maximum one of its children
+#[derive(Debug)]
+pub struct MinMaxHeap<T> {
+    data: Vec<T>,
+}
+
+fn is_min_level(idx: usize) -> bool {
+    // level of idx is floor(log2(idx + 1))
+    (usize::BITS - 1 - (idx + 1).leading_zeros()).is_multiple_of(2)
+}
+
+impl<T> MinMaxHeap<T> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len() == 0
+    }
+
+    /// Returns the underlying vector in arbitrary order
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
+
+impl<T> MinMaxHeap<T>
+where
+    T: PartialOrd,
+{
+    pub fn new() -> Self {
+        MinMaxHeap { data: Vec::new() }
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        MinMaxHeap {
+            data: Vec::with_capacity(cap),
+        }
+    }
+
+    fn max_idx(&self) -> Option<usize> {
+        match self.data.len() {
+            0 => None,
+            1 => Some(0),
+            2 => Some(1),
+            _ => Some(if self.data[1] < self.data[2] { 2 } else { 1 }),
+        }
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn peek_max(&self) -> Option<&T> {
+        self.max_idx().map(|idx| &self.data[idx])
+    }
+
+    pub fn push(&mut self, value: T) {
+        let old_len = self.data.len();
+        self.data.push(value);
+        self.sift_up(old_len);
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.remove_at(0)
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        let idx = self.max_idx()?;
+        self.remove_at(idx)
+    }
+
+    /// Replaces the minimum with value, returning the old minimum
+    pub fn replace_min(&mut self, value: T) -> Option<T> {
+        if self.is_empty() {
+            self.push(value);
+            return None;
+        }
+        let old = replace(&mut self.data[0], value);
+        self.sift_down(0);
+        Some(old)
+    }
+
+    /// Replaces the maximum with value, returning the old maximum
+    pub fn replace_max(&mut self, value: T) -> Option<T> {
+        let idx = match self.max_idx() {
+            Some(idx) => idx,
+            None => {
+                self.push(value);
+                return None;
+            }
+        };
+        let old = replace(&mut self.data[idx], value);
+        // the new value may belong above its min level parent
+        if idx > 0 && self.data[idx] < self.data[0] {
+            self.data.swap(0, idx);
+        }
+        self.sift_down(idx);
+        Some(old)
+    }
+
+    /// Pushes value and pops the minimum, faster than doing both in a row
+    pub fn push_pop_min(&mut self, value: T) -> T {
+        match self.peek_min() {
+            Some(min) if *min < value => self.replace_min(value).unwrap(),
+            _ => value,
+        }
+    }
+
+    /// Pushes value and pops the maximum, faster than doing both in a row
+    pub fn push_pop_max(&mut self, value: T) -> T {
+        match self.peek_max() {
+            Some(max) if value < *max => self.replace_max(value).unwrap(),
+            _ => value,
+        }
+    }
+
+    /// Returns the elements in ascending order
+    pub fn into_vec_asc(mut self) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.len());
+        while let Some(el) = self.pop_min() {
+            vec.push(el);
+        }
+        vec
+    }
+
+    /// Returns the elements in descending order
+    pub fn into_vec_desc(mut self) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.len());
+        while let Some(el) = self.pop_max() {
+            vec.push(el);
+        }
+        vec
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Option<T> {
+        self.data.pop().map(|mut el| {
+            if idx < self.data.len() {
+                swap(&mut el, &mut self.data[idx]);
+                self.sift_down(idx);
+            }
+            el
+        })
+    }
+
+    fn sift_up(&mut self, idx: usize) {
+        if idx == 0 {
+            return;
+        }
+
+        let mut hole = Hole::new(&mut self.data, idx);
+        let parent = (idx - 1) / 2;
+
+        // parent is on the opposite kind of level,
+        // first decide which kind of level the element belongs to
+        let min = if is_min_level(idx) {
+            if hole.get(parent) < hole.el() {
+                hole.move_to(parent);
+                false
+            } else {
+                true
+            }
+        } else if hole.el() < hole.get(parent) {
+            hole.move_to(parent);
+            true
+        } else {
+            false
+        };
+
+        // then bubble up through the grandparents
+        while hole.idx() >= 3 {
+            let grandparent = (hole.idx() - 3) / 4;
+            let above = if min {
+                hole.el() < hole.get(grandparent)
+            } else {
+                hole.get(grandparent) < hole.el()
+            };
+            if !above {
+                break;
+            }
+            hole.move_to(grandparent);
+        }
+    }
+
+    fn sift_down(&mut self, idx: usize) {
+        let min = is_min_level(idx);
+        let end = self.data.len();
+        let mut hole = Hole::new(&mut self.data, idx);
+
+        // a comes before b on this kind of level
+        let before = |a: &T, b: &T| if min { a < b } else { b < a };
+
+        loop {
+            let idx = hole.idx();
+            let child = 2 * idx + 1;
+            if child >= end {
+                break;
+            }
+
+            // smallest (largest) of the children and grandchildren
+            let grandchild = 4 * idx + 3;
+            let mut m = child;
+            for cand in [
+                child + 1,
+                grandchild,
+                grandchild + 1,
+                grandchild + 2,
+                grandchild + 3,
+            ] {
+                if cand < end && before(hole.get(cand), hole.get(m)) {
+                    m = cand;
+                }
+            }
+
+            if !before(hole.get(m), hole.el()) {
+                break;
+            }
+            hole.move_to(m);
+
+            if m < grandchild {
+                // children have no descendants on our kind of level
+                break;
+            }
+
+            // the parent of m is on the opposite kind of level
+            let parent = (m - 1) / 2;
+            if before(hole.get(parent), hole.el()) {
+                hole.swap_with(parent);
+            }
+        }
+    }
+
+    /// Restores the heap property for the whole data in O(n)
+    fn rebuild(&mut self) {
+        let mut idx = self.len() / 2;
+        while idx > 0 {
+            idx -= 1;
+            self.sift_down(idx);
+        }
+    }
+}
+
+impl<T: PartialOrd> Default for MinMaxHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialOrd> From<Vec<T>> for MinMaxHeap<T> {
+    /// Heapifies the vector in O(n)
+    fn from(data: Vec<T>) -> Self {
+        let mut heap = MinMaxHeap { data };
+        heap.rebuild();
+        heap
+    }
+}
+
+impl<T: PartialOrd> FromIterator<T> for MinMaxHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut data = Vec::new();
+        for el in iter {
+            data.push(el);
+        }
+        MinMaxHeap::from(data)
+    }
+}
+
+impl<T: PartialOrd> Extend<T> for MinMaxHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for el in iter {
+            self.push(el);
+        }
+    }
+}
+
+#[cfg(test)]
+impl<T: PartialOrd> MinMaxHeap<T> {
+    /// checks every node against all of its descendants
+    fn check(&self) {
+        for idx in 0..self.len() {
+            let mut stack = vec![2 * idx + 1, 2 * idx + 2];
+            while let Some(desc) = stack.pop() {
+                if desc >= self.len() {
+                    continue;
+                }
+                if is_min_level(idx) {
+                    assert!(self.data[idx] <= self.data[desc]);
+                } else {
+                    assert!(self.data[desc] <= self.data[idx]);
+                }
+                stack.push(2 * desc + 1);
+                stack.push(2 * desc + 2);
+            }
+        }
+    }
+}
+
+#[test]
+fn min_max_heap_test_push_pop() {
+    let mut hp = MinMaxHeap::new();
+    assert_eq!(hp.peek_min(), None);
+    assert_eq!(hp.peek_max(), None);
+
+    for x in [5, 1, 9, 3, 7, 2, 8, 6, 4, 0] {
+        hp.push(x);
+        hp.check();
+    }
+    assert_eq!(hp.peek_min(), Some(&0));
+    assert_eq!(hp.peek_max(), Some(&9));
+
+    assert_eq!(hp.pop_max(), Some(9));
+    assert_eq!(hp.pop_min(), Some(0));
+    assert_eq!(hp.pop_max(), Some(8));
+    assert_eq!(hp.pop_min(), Some(1));
+    hp.check();
+    assert_eq!(&*hp.into_vec_asc(), &[2, 3, 4, 5, 6, 7]);
+
+    let hp: MinMaxHeap<i32> = (0..50).map(|x| (x * 37) % 50).collect();
+    hp.check();
+    let desc: std::vec::Vec<i32> = (0..50).rev().collect();
+    assert_eq!(&*hp.into_vec_desc(), &desc[..]);
+}
+
+#[test]
+fn min_max_heap_test_replace() {
+    let mut hp: MinMaxHeap<i32> = (10..20).collect();
+
+    // values past the ends come straight back
+    assert_eq!(hp.push_pop_max(25), 25);
+    assert_eq!(hp.push_pop_min(5), 5);
+
+    assert_eq!(hp.push_pop_max(15), 19);
+    assert_eq!(hp.push_pop_min(12), 10);
+    assert_eq!(hp.replace_max(0), Some(18));
+    hp.check();
+    assert_eq!(hp.peek_min(), Some(&0));
+    assert_eq!(hp.replace_min(100), Some(0));
+    hp.check();
+    assert_eq!(hp.peek_max(), Some(&100));
+
+    assert_eq!(
+        &*hp.into_vec_asc(),
+        &[11, 12, 12, 13, 14, 15, 15, 16, 17, 100]
+    );
+
+    let mut hp = MinMaxHeap::new();
+    assert_eq!(hp.replace_max(1), None);
+    assert_eq!(hp.replace_max(2), Some(1));
+    assert_eq!(hp.replace_min(3), Some(2));
+    assert_eq!(hp.len(), 1);
+}
+
+#[test]
+fn min_max_heap_test_random_ops() {
+    let mut hp = MinMaxHeap::new();
+    let mut reference = std::vec::Vec::new();
+
+    // xorshift, good enough to shuffle operations around
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut rand = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for _ in 0..3000 {
+        let value = rand() % 200;
+        match rand() % 6 {
+            0 | 1 => {
+                hp.push(value);
+                reference.push(value);
+            }
+            2 => {
+                reference.sort();
+                let expected = if reference.is_empty() {
+                    None
+                } else {
+                    Some(reference.remove(0))
+                };
+                assert_eq!(hp.pop_min(), expected);
+            }
+            3 => {
+                reference.sort();
+                assert_eq!(hp.pop_max(), reference.pop());
+            }
+            4 => {
+                reference.push(value);
+                reference.sort();
+                assert_eq!(hp.push_pop_max(value), reference.pop().unwrap());
+            }
+            _ => {
+                reference.push(value);
+                reference.sort();
+                assert_eq!(hp.push_pop_min(value), reference.remove(0));
+            }
+        }
+        hp.check();
+        assert_eq!(hp.len(), reference.len());
+    }
+}
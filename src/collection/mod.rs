@@ -1,5 +1,10 @@
+pub mod binomial_heap;
 pub mod deque;
 pub mod hashmap;
 pub mod heap;
+pub mod indexed_heap;
+pub mod min_max_heap;
+pub mod pairing_heap;
 pub mod raw;
+pub mod rc;
 pub mod vec;
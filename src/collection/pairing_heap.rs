@@ -0,0 +1,520 @@
+use std::{
+    cell::Cell,
+    mem,
+    ptr::{self, null_mut},
+    rc::Rc,
+};
+
+use super::{
+    heap::{Compare, HeapId, MaxOrder, MinOrder, PriorityQueue},
+    vec::Vec,
+};
+
+struct Node<T> {
+    value: T,
+    slot: Option<Rc<Slot<T>>>,
+    /// first child
+    child: *mut Node<T>,
+    /// right sibling
+    next: *mut Node<T>,
+    /// parent if this is the first child, left sibling otherwise
+    prev: *mut Node<T>,
+}
+
+/// Shared between a node and its handles
+struct Slot<T> {
+    /// null once the element left the heap
+    node: Cell<*mut Node<T>>,
+    owner: Rc<HeapId>,
+}
+
+/// Refers to an element pushed with `PairingHeap::push_with_handle`
+///
+/// Stays valid through melds, and is simply ignored once the element
+/// was popped or the heap dropped
+pub struct Handle<T> {
+    slot: Rc<Slot<T>>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+/// Pairing heap, O(1) push and meld, O(log n) amortized pop
+pub struct PairingHeap<T, C = MaxOrder> {
+    root: *mut Node<T>,
+    len: usize,
+    id: Rc<HeapId>,
+    cmp: C,
+}
+
+impl<T> PairingHeap<T>
+where
+    T: PartialOrd,
+{
+    pub fn new() -> Self {
+        PairingHeap::with_order(MaxOrder)
+    }
+}
+
+impl<T> PairingHeap<T, MinOrder>
+where
+    T: PartialOrd,
+{
+    /// Creates a heap with the smallest element on top
+    pub fn new_min() -> Self {
+        PairingHeap::with_order(MinOrder)
+    }
+}
+
+impl<T, C> PairingHeap<T, C> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*self.root).value) }
+        }
+    }
+
+    /// Returns the node of handle if it is still in this heap
+    fn node(&self, handle: &Handle<T>) -> Option<*mut Node<T>> {
+        let node = handle.slot.node.get();
+        if node.is_null() || !HeapId::same(&handle.slot.owner, &self.id) {
+            None
+        } else {
+            Some(node)
+        }
+    }
+
+    /// Returns the element of handle, None if it is not in this heap
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.node(handle).map(|node| unsafe { &(*node).value })
+    }
+}
+
+impl<T, C> PairingHeap<T, C>
+where
+    C: Compare<T>,
+{
+    pub fn with_order(cmp: C) -> Self {
+        PairingHeap {
+            root: null_mut(),
+            len: 0,
+            id: HeapId::new(),
+            cmp,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.push_node(value, None);
+    }
+
+    /// Pushes value and returns a handle for `decrease_key`
+    pub fn push_with_handle(&mut self, value: T) -> Handle<T> {
+        let slot = Rc::new(Slot {
+            node: Cell::new(null_mut()),
+            owner: self.id.clone(),
+        });
+        let node = self.push_node(value, Some(slot.clone()));
+        slot.node.set(node);
+        Handle { slot }
+    }
+
+    fn push_node(&mut self, value: T, slot: Option<Rc<Slot<T>>>) -> *mut Node<T> {
+        // compare before allocating, a panic then only drops value
+        let root_wins = !self.root.is_null() && unsafe { self.cmp.le(&value, &(*self.root).value) };
+        let node = Box::into_raw(Box::new(Node {
+            value,
+            slot,
+            child: null_mut(),
+            next: null_mut(),
+            prev: null_mut(),
+        }));
+        self.root = unsafe {
+            if self.root.is_null() {
+                node
+            } else if root_wins {
+                adopt(self.root, node)
+            } else {
+                adopt(node, self.root)
+            }
+        };
+        self.len += 1;
+        node
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.root.is_null() {
+            return None;
+        }
+
+        // the heap must not point to the freed root while the children
+        // are paired up, a comparison may panic
+        let root = unsafe { Box::from_raw(mem::replace(&mut self.root, null_mut())) };
+        let Node {
+            value, slot, child, ..
+        } = *root;
+        if let Some(slot) = slot {
+            slot.node.set(null_mut());
+        }
+        self.len -= 1;
+
+        unsafe { combine(&self.cmp, &mut self.root, child) };
+        Some(value)
+    }
+
+    /// Moves all elements of other into self in O(1)
+    pub fn meld(&mut self, mut other: Self) {
+        HeapId::forward(&other.id, &self.id);
+        if other.root.is_null() {
+            return;
+        }
+
+        // compare while both trees are still owned by their heap
+        let self_wins = !self.root.is_null()
+            && unsafe { self.cmp.le(&(*other.root).value, &(*self.root).value) };
+        let root = mem::replace(&mut other.root, null_mut());
+        self.root = unsafe {
+            if self.root.is_null() {
+                root
+            } else if self_wins {
+                adopt(self.root, root)
+            } else {
+                adopt(root, self.root)
+            }
+        };
+        self.len += mem::take(&mut other.len);
+    }
+
+    /// Replaces the element of handle with value, which has to be at least
+    /// as close to the top (for `MinOrder` a smaller or equal one)
+    ///
+    /// Returns false and leaves the heap untouched if value would move the
+    /// element down, or if handle is not in this heap
+    pub fn decrease_key(&mut self, handle: &Handle<T>, value: T) -> bool {
+        let node = match self.node(handle) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        unsafe {
+            if !self.cmp.le(&(*node).value, &value) {
+                return false;
+            }
+            (*node).value = value;
+
+            if node != self.root {
+                // cut the subtree and link it back at the root, compare
+                // first so a panic leaves the tree in one piece
+                let root_wins = self.cmp.le(&(*node).value, &(*self.root).value);
+                self.cut(node);
+                self.root = if root_wins {
+                    adopt(self.root, node)
+                } else {
+                    adopt(node, self.root)
+                };
+            }
+        }
+        true
+    }
+
+    /// Detaches the subtree of node from its parent and siblings
+    unsafe fn cut(&mut self, node: *mut Node<T>) {
+        let prev = (*node).prev;
+        let next = (*node).next;
+
+        if (*prev).child == node {
+            (*prev).child = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+
+        (*node).next = null_mut();
+        (*node).prev = null_mut();
+    }
+}
+
+/// Makes child the first child of parent, both are roots
+unsafe fn adopt<T>(parent: *mut Node<T>, child: *mut Node<T>) -> *mut Node<T> {
+    (*child).next = (*parent).child;
+    if !(*parent).child.is_null() {
+        (*(*parent).child).prev = child;
+    }
+    (*child).prev = parent;
+    (*parent).child = child;
+    parent
+}
+
+/// Trees of a `combine` in progress
+///
+/// Writes the root back when dropped. If a comparison panicked, the trees
+/// not combined yet are hung under one root without comparing: the heap
+/// order is off then, but no node is lost
+struct Pairing<'a, T> {
+    root: &'a mut *mut Node<T>,
+    /// result of the second pass so far
+    acc: *mut Node<T>,
+    /// pairs of the first pass, and the siblings not paired yet
+    trees: Vec<*mut Node<T>>,
+    rest: *mut Node<T>,
+}
+
+impl<T> Drop for Pairing<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            while !self.rest.is_null() {
+                let node = self.rest;
+                self.rest = (*node).next;
+                (*node).next = null_mut();
+                (*node).prev = null_mut();
+                self.trees.push(node);
+            }
+
+            let mut root = self.acc;
+            while let Some(tree) = self.trees.pop() {
+                root = if root.is_null() {
+                    tree
+                } else {
+                    adopt(root, tree)
+                };
+            }
+            *self.root = root;
+        }
+    }
+}
+
+/// Two pass pairing of the sibling list starting at first into root,
+/// which is null
+unsafe fn combine<T, C: Compare<T>>(cmp: &C, root: &mut *mut Node<T>, first: *mut Node<T>) {
+    let mut pairing = Pairing {
+        root,
+        acc: null_mut(),
+        trees: Vec::new(),
+        rest: first,
+    };
+
+    // first pass: link pairs from left to right
+    while !pairing.rest.is_null() {
+        let a = pairing.rest;
+        let b = (*a).next;
+        if b.is_null() {
+            pairing.rest = null_mut();
+            (*a).prev = null_mut();
+            pairing.trees.push(a);
+            break;
+        }
+
+        // everything stays in rest until the comparison went through
+        let a_wins = cmp.le(&(*b).value, &(*a).value);
+        pairing.rest = (*b).next;
+        for node in [a, b] {
+            (*node).next = null_mut();
+            (*node).prev = null_mut();
+        }
+        pairing
+            .trees
+            .push(if a_wins { adopt(a, b) } else { adopt(b, a) });
+    }
+
+    // second pass: link the pairs from right to left
+    pairing.acc = pairing.trees.pop().unwrap_or(null_mut());
+    while let Some(&tree) = pairing.trees.last() {
+        let acc = pairing.acc;
+        let tree_wins = cmp.le(&(*acc).value, &(*tree).value);
+        pairing.trees.pop();
+        pairing.acc = if tree_wins {
+            adopt(tree, acc)
+        } else {
+            adopt(acc, tree)
+        };
+    }
+}
+
+impl<T, C> Drop for PairingHeap<T, C> {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        if !self.root.is_null() {
+            stack.push(self.root);
+        }
+
+        while let Some(node) = stack.pop() {
+            let node = unsafe { Box::from_raw(node) };
+            if !node.child.is_null() {
+                stack.push(node.child);
+            }
+            if !node.next.is_null() {
+                stack.push(node.next);
+            }
+            if let Some(slot) = &node.slot {
+                slot.node.set(null_mut());
+            }
+        }
+        self.root = ptr::null_mut();
+    }
+}
+
+impl<T, C: Compare<T> + Default> Default for PairingHeap<T, C> {
+    fn default() -> Self {
+        Self::with_order(C::default())
+    }
+}
+
+impl<T, C: Compare<T>> PriorityQueue<T> for PairingHeap<T, C> {
+    fn push(&mut self, value: T) {
+        PairingHeap::push(self, value)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        PairingHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        PairingHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        PairingHeap::len(self)
+    }
+
+    fn meld(&mut self, other: Self) {
+        PairingHeap::meld(self, other)
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for PairingHeap<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for el in iter {
+            self.push(el);
+        }
+    }
+}
+
+impl<T, C: Compare<T> + Default> FromIterator<T> for PairingHeap<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::default();
+        heap.extend(iter);
+        heap
+    }
+}
+
+#[test]
+fn pairing_heap_test_push_pop() {
+    let mut hp: PairingHeap<i32> = [5, 3, 9, 1, 7, 3].into_iter().collect();
+    assert_eq!(hp.len(), 6);
+    assert_eq!(hp.peek(), Some(&9));
+
+    let mut other = PairingHeap::new();
+    other.extend(10..15);
+    hp.meld(other);
+
+    let popped: std::vec::Vec<_> = std::iter::from_fn(|| hp.pop()).collect();
+    assert_eq!(popped, [14, 13, 12, 11, 10, 9, 7, 5, 3, 3, 1]);
+    assert_eq!(hp.pop(), None);
+}
+
+#[test]
+fn pairing_heap_test_decrease_key() {
+    let mut hp = PairingHeap::new_min();
+    let handles: std::vec::Vec<_> = (0..20).map(|x| hp.push_with_handle(x * 10)).collect();
+    assert_eq!(hp.pop(), Some(0));
+
+    // popped elements and moves in the wrong direction are refused
+    assert!(!hp.decrease_key(&handles[0], -1));
+    assert!(!hp.decrease_key(&handles[5], 60));
+
+    assert!(hp.decrease_key(&handles[15], 5));
+    assert!(hp.decrease_key(&handles[7], 1));
+    assert_eq!(hp.get(&handles[15]), Some(&5));
+    assert_eq!(hp.pop(), Some(1));
+    assert_eq!(hp.pop(), Some(5));
+    assert_eq!(hp.get(&handles[15]), None);
+
+    // handles survive melds, and are rejected by unrelated heaps
+    let mut other = PairingHeap::new_min();
+    let h = other.push_with_handle(100);
+    assert!(!hp.decrease_key(&h, 0));
+    hp.meld(other);
+    assert!(hp.decrease_key(&h, 0));
+    assert!(hp.decrease_key(&handles[19], 3));
+
+    let popped: std::vec::Vec<_> = std::iter::from_fn(|| hp.pop()).collect();
+    assert_eq!(
+        popped,
+        [0, 3, 10, 20, 30, 40, 50, 60, 80, 90, 100, 110, 120, 130, 140, 160, 170, 180]
+    );
+}
+
+#[test]
+fn pairing_heap_test_drop() {
+    let counter = Rc::new(());
+    let mut hp = PairingHeap::with_order(super::heap::KeyOrder(|el: &(i32, Rc<()>)| el.0));
+    for i in 0..50 {
+        hp.push((i, counter.clone()));
+    }
+    let handle = hp.push_with_handle((100, counter.clone()));
+    for _ in 0..10 {
+        hp.pop();
+    }
+    assert_eq!(Rc::strong_count(&counter), 42);
+    drop(hp);
+    assert_eq!(Rc::strong_count(&counter), 1);
+    drop(handle);
+}
+
+#[test]
+fn pairing_heap_test_panic_safety() {
+    use super::heap::{Bomb, BOMBS, FUSE};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // a panic at every possible comparison in turn, until the ops go through
+    for fuse in 1.. {
+        BOMBS.with(|bombs| bombs.borrow_mut().clear());
+        let mut hp: PairingHeap<Bomb> = (0..20).map(|i| Bomb::new((i * 7) % 13)).collect();
+        let handle = hp.push_with_handle(Bomb::new(5));
+
+        FUSE.with(|f| f.set(fuse));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            hp.pop();
+            hp.push(Bomb::new(9));
+            hp.decrease_key(&handle, Bomb::new(30));
+            let mut other: PairingHeap<Bomb> = (0..5).map(Bomb::new).collect();
+            other.pop();
+            hp.meld(other);
+            for _ in 0..6 {
+                hp.pop();
+            }
+        }));
+        FUSE.with(|f| f.set(0));
+
+        // whatever order is left, every element is either popped from the
+        // heap or was dropped, exactly once
+        let len = hp.len();
+        let mut popped = 0;
+        while hp.pop().is_some() {
+            popped += 1;
+        }
+        assert_eq!(popped, len, "fuse {fuse}");
+        assert!(hp.get(&handle).is_none());
+        drop(hp);
+        BOMBS.with(|bombs| assert!(bombs.borrow().iter().all(|&drops| drops == 1)));
+
+        if result.is_ok() {
+            break;
+        }
+    }
+}
@@ -1,2 +1,3 @@
 pub mod raw_iter;
 pub mod raw_vec;
+pub(crate) mod refcount;
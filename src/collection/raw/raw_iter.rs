@@ -9,6 +9,11 @@ pub struct RawIter<T> {
 }
 
 impl<T> RawIter<T> {
+    /// # Safety
+    ///
+    /// The returned iterator reads elements out of `slice` by value, so the
+    /// caller must make sure the slice outlives the iterator and that its
+    /// elements are not used (or dropped) again after being yielded.
     pub unsafe fn new(slice: &[T]) -> Self {
         RawIter {
             start: slice.as_ptr(),
@@ -16,7 +21,7 @@ impl<T> RawIter<T> {
                 // if T is zst, cast pointer to usize, increment, and then
                 // cast it back
                 ((slice.as_ptr() as usize) + slice.len()) as *const _
-            } else if slice.len() == 0 {
+            } else if slice.is_empty() {
                 slice.as_ptr()
             } else {
                 slice.as_ptr().add(slice.len())
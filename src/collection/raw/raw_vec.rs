@@ -26,6 +26,11 @@ impl<T> RawVec<T> {
     }
 
     pub fn with_capacity(cap: usize) -> Self {
+        if mem::size_of::<T>() == 0 {
+            // zero sized types never allocate
+            return Self::new();
+        }
+
         let mut real_cap = 1;
         while real_cap < cap {
             real_cap *= 2;
@@ -81,6 +86,11 @@ impl<T> RawVec<T> {
         self.cap = new_cap
     }
 }
+impl<T> Default for RawVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl<T> Drop for RawVec<T> {
     fn drop(&mut self) {
         let is_zst = mem::size_of::<T>() == 0;
@@ -0,0 +1,32 @@
+//! Allocation helpers shared by `collection::rc::Rc` and `sync::arc::Arc`
+//!
+//! Both allocate a repr(C) header of counters H with the data right
+//! behind it, so the offset of the data only depends on its alignment
+
+use std::{alloc::Layout, ptr};
+
+/// Layout of a header H followed by data with the given layout
+pub(crate) fn inner_layout<H>(data: Layout) -> Layout {
+    Layout::new::<H>().extend(data).unwrap().0.pad_to_align()
+}
+
+/// Layout of the data behind data, which may already be dropped
+///
+/// Size and alignment of a slice, str or trait object come from the
+/// pointer metadata alone, the value itself is never read
+pub(crate) unsafe fn data_layout<T: ?Sized>(data: *const T) -> Layout {
+    Layout::for_value(&*data)
+}
+
+/// Offset of the data behind a header H, for data aligned to align
+pub(crate) fn data_offset<H>(align: usize) -> usize {
+    let data = Layout::from_size_align(0, align).unwrap();
+    Layout::new::<H>().extend(data).unwrap().1
+}
+
+/// Replaces the address of a possibly fat pointer, keeping its metadata
+pub(crate) unsafe fn set_data_ptr<T: ?Sized>(mut ptr: *mut T, data: *mut u8) -> *mut T {
+    // the address is the first word of any pointer
+    ptr::write(&mut ptr as *mut *mut T as *mut *mut u8, data);
+    ptr
+}
@@ -0,0 +1,663 @@
+use std::{
+    alloc::{self, Layout},
+    borrow::Borrow,
+    cell::Cell,
+    cmp, fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::Deref,
+    ptr::{self, NonNull},
+};
+
+use super::raw::refcount::{data_layout, data_offset, inner_layout, set_data_ptr};
+
+/// Single threaded reference counted pointer, the non atomic `sync::arc::Arc`
+///
+/// Neither `Send` nor `Sync`, the counters are plain cells:
+///
+/// ```compile_fail
+/// use collection::collection::rc::Rc;
+///
+/// let rc = Rc::new(1);
+/// std::thread::spawn(move || drop(rc));
+/// ```
+pub struct Rc<T: ?Sized> {
+    // NonNull is !Send and !Sync, which keeps Rc on its thread
+    ptr: NonNull<RcInner<T>>,
+    phantom: PhantomData<RcInner<T>>,
+}
+
+/// Non owning reference to the data of an `Rc`
+///
+/// Does not keep the data alive, only the allocation
+pub struct Weak<T: ?Sized> {
+    // dangling (usize::MAX) for `Weak::new`, no allocation behind it
+    ptr: NonNull<RcInner<T>>,
+}
+
+// same layout as `ArcInner`, the data sits behind the counters
+#[repr(C)]
+struct RcInner<T: ?Sized> {
+    strong: Cell<usize>,
+    // weak references + 1, all strong references together hold one weak
+    weak: Cell<usize>,
+    data: T,
+}
+
+/// The counters of an `RcInner`, reachable through a `Weak` even after
+/// the data was dropped
+struct WeakInner<'a> {
+    strong: &'a Cell<usize>,
+    weak: &'a Cell<usize>,
+}
+
+/// Counter updates shared by `RcInner` and `WeakInner`
+trait Counters {
+    fn strong(&self) -> &Cell<usize>;
+    fn weak(&self) -> &Cell<usize>;
+
+    fn inc_strong(&self) {
+        // overflowing means we leaked a lot of references, bail out
+        let strong = self.strong().get().wrapping_add(1);
+        self.strong().set(strong);
+        if strong == 0 {
+            std::process::abort();
+        }
+    }
+
+    fn dec_strong(&self) {
+        self.strong().set(self.strong().get() - 1);
+    }
+
+    fn inc_weak(&self) {
+        let weak = self.weak().get().wrapping_add(1);
+        self.weak().set(weak);
+        if weak == 0 {
+            std::process::abort();
+        }
+    }
+
+    fn dec_weak(&self) {
+        self.weak().set(self.weak().get() - 1);
+    }
+}
+
+impl<T: ?Sized> Counters for RcInner<T> {
+    fn strong(&self) -> &Cell<usize> {
+        &self.strong
+    }
+
+    fn weak(&self) -> &Cell<usize> {
+        &self.weak
+    }
+}
+
+impl Counters for WeakInner<'_> {
+    fn strong(&self) -> &Cell<usize> {
+        self.strong
+    }
+
+    fn weak(&self) -> &Cell<usize> {
+        self.weak
+    }
+}
+
+impl<T> Rc<T> {
+    pub fn new(data: T) -> Rc<T> {
+        let boxed = Box::new(RcInner {
+            strong: Cell::new(1),
+            weak: Cell::new(1),
+            data,
+        });
+
+        Rc {
+            ptr: NonNull::new(Box::into_raw(boxed)).unwrap(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Mutable access to the data, clones it into a new allocation first
+    /// if other `Rc`s point to it
+    ///
+    /// `Weak`s pointing to a uniquely owned data are disassociated instead
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if Rc::strong_count(this) != 1 {
+            *this = Rc::new((**this).clone());
+        } else if Rc::weak_count(this) != 0 {
+            // only `Weak`s are left, move the data out and let them see a
+            // dropped value, without running the drop of `this`
+            unsafe {
+                let data = ptr::read(&this.inner().data);
+                this.inner().dec_strong();
+                // can't reach 0, the `Weak`s still hold the allocation
+                this.inner().dec_weak();
+                ptr::write(this, Rc::new(data));
+            }
+        }
+
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+
+    /// Returns the data if this is the only `Rc`, otherwise gives it back
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if Rc::strong_count(&this) != 1 {
+            return Err(this);
+        }
+
+        let this = ManuallyDrop::new(this);
+        let data = unsafe { ptr::read(&this.inner().data) };
+        this.inner().dec_strong();
+        drop(Weak { ptr: this.ptr });
+        Ok(data)
+    }
+
+    /// Drops this `Rc`, returning the data if it was the last one
+    pub fn into_inner(this: Self) -> Option<T> {
+        Rc::try_unwrap(this).ok()
+    }
+
+    /// Returns the data if this is the only `Rc`, otherwise a clone of it
+    pub fn unwrap_or_clone(this: Self) -> T
+    where
+        T: Clone,
+    {
+        Rc::try_unwrap(this).unwrap_or_else(|rc| (*rc).clone())
+    }
+}
+
+impl<T: ?Sized> Rc<T> {
+    fn inner(&self) -> &RcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Allocates an `RcInner` for data with the given layout and sets up
+    /// the counters, the data is left uninitialized
+    ///
+    /// mem_to_inner turns the allocation into a (possibly fat) pointer
+    unsafe fn allocate_for_layout(
+        data: Layout,
+        mem_to_inner: impl FnOnce(*mut u8) -> *mut RcInner<T>,
+    ) -> *mut RcInner<T> {
+        let layout = inner_layout::<RcInner<()>>(data);
+        let mem = alloc::alloc(layout);
+        if mem.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        let inner = mem_to_inner(mem);
+        ptr::addr_of_mut!((*inner).strong).write(Cell::new(1));
+        ptr::addr_of_mut!((*inner).weak).write(Cell::new(1));
+        inner
+    }
+
+    /// Moves the content of a box into a new `Rc`
+    ///
+    /// Works for unsized content, e.g. `Box<dyn Trait>` into `Rc<dyn Trait>`
+    pub fn from_box(boxed: Box<T>) -> Rc<T> {
+        unsafe {
+            let data_layout = Layout::for_value(&*boxed);
+            let bptr = Box::into_raw(boxed);
+            let inner = Rc::allocate_for_layout(data_layout, |mem| {
+                set_data_ptr(bptr as *mut RcInner<T>, mem)
+            });
+
+            // move the value over, then free the box without dropping it
+            ptr::copy_nonoverlapping(
+                bptr as *const u8,
+                ptr::addr_of_mut!((*inner).data) as *mut u8,
+                data_layout.size(),
+            );
+            if data_layout.size() != 0 {
+                alloc::dealloc(bptr as *mut u8, data_layout);
+            }
+
+            Rc {
+                ptr: NonNull::new_unchecked(inner),
+                phantom: PhantomData,
+            }
+        }
+    }
+
+    /// Creates a new `Weak` pointer to this allocation
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        this.inner().inc_weak();
+        Weak { ptr: this.ptr }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    /// Number of `Weak` pointers, without the one held by the strong ones
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get() - 1
+    }
+
+    /// Mutable access to the data if no other `Rc` or `Weak` points to it
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Rc::strong_count(this) == 1 && Rc::weak_count(this) == 0 {
+            unsafe { Some(&mut (*this.ptr.as_ptr()).data) }
+        } else {
+            None
+        }
+    }
+
+    /// True if both point to the same allocation
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).data) }
+    }
+
+    /// Consumes the `Rc` without touching the counts,
+    /// use `from_raw` to get it back
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Rc::as_ptr(&this);
+        mem::forget(this);
+        ptr
+    }
+
+    /// Rebuilds an `Rc` from `into_raw`
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Rc::<U>::into_raw`, where U has the same size
+    /// and alignment as T (or is T), and each call takes over one strong
+    /// reference
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let offset = data_offset::<RcInner<()>>(mem::align_of_val(&*ptr));
+        let inner = ptr.byte_sub(offset) as *mut RcInner<T>;
+
+        Rc {
+            ptr: NonNull::new_unchecked(inner),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Adds a strong reference to the allocation of ptr
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Rc::<T>::into_raw`, and the allocation has
+    /// to be alive
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let rc = ManuallyDrop::new(Rc::from_raw(ptr));
+        let _clone: ManuallyDrop<_> = rc.clone();
+    }
+
+    /// Drops a strong reference to the allocation of ptr
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Rc::<T>::into_raw`, and the strong reference
+    /// given up has to be owned by the caller
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Rc::from_raw(ptr));
+    }
+}
+
+impl<T> From<std::vec::Vec<T>> for Rc<[T]> {
+    fn from(vec: std::vec::Vec<T>) -> Self {
+        unsafe {
+            let len = vec.len();
+            let inner = Rc::allocate_for_layout(Layout::array::<T>(len).unwrap(), |mem| {
+                ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut RcInner<[T]>
+            });
+            ptr::copy_nonoverlapping(
+                vec.as_ptr(),
+                ptr::addr_of_mut!((*inner).data) as *mut T,
+                len,
+            );
+
+            // the elements were moved, only free the buffer
+            let mut vec = ManuallyDrop::new(vec);
+            vec.set_len(0);
+            ManuallyDrop::drop(&mut vec);
+
+            Rc {
+                ptr: NonNull::new_unchecked(inner),
+                phantom: PhantomData,
+            }
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Rc<[T]> {
+    fn from(slice: &[T]) -> Self {
+        Rc::from(slice.to_vec())
+    }
+}
+
+impl<T> FromIterator<T> for Rc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().collect::<std::vec::Vec<T>>().into()
+    }
+}
+
+impl From<&str> for Rc<str> {
+    fn from(s: &str) -> Self {
+        let bytes: Rc<[u8]> = Rc::from(s.as_bytes());
+        // str has the same layout as [u8]
+        unsafe { Rc::from_raw(Rc::into_raw(bytes) as *const str) }
+    }
+}
+
+impl From<String> for Rc<str> {
+    fn from(s: String) -> Self {
+        Rc::from(&s[..])
+    }
+}
+
+impl<T: ?Sized> Deref for Rc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner().data
+    }
+}
+
+impl<T: ?Sized> Clone for Rc<T> {
+    fn clone(&self) -> Self {
+        self.inner().inc_strong();
+
+        Self {
+            ptr: self.ptr,
+            phantom: self.phantom,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Rc<T> {
+    fn drop(&mut self) {
+        let inner = self.inner();
+        inner.dec_strong();
+        if inner.strong.get() != 0 {
+            return;
+        }
+
+        unsafe {
+            ptr::drop_in_place(&mut (*self.ptr.as_ptr()).data);
+        }
+
+        // release the weak reference held by the strong ones,
+        // frees the allocation if no `Weak` is left
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Rc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Rc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for Rc<T> {
+    /// Formats the address of the data
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&Rc::as_ptr(self), f)
+    }
+}
+
+impl<T: Default> Default for Rc<T> {
+    fn default() -> Self {
+        Rc::new(T::default())
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Rc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Rc<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for Rc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for Rc<T> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Rc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Rc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Rc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> From<T> for Rc<T> {
+    fn from(data: T) -> Self {
+        Rc::new(data)
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Rc<T> {
+    fn from(boxed: Box<T>) -> Self {
+        Rc::from_box(boxed)
+    }
+}
+
+// the data never moves, only the pointer to it
+impl<T: ?Sized> Unpin for Rc<T> {}
+
+impl<T> Weak<T> {
+    /// Creates a `Weak` without an allocation, it never upgrades
+    pub fn new() -> Weak<T> {
+        Weak {
+            ptr: NonNull::new(usize::MAX as *mut RcInner<T>).unwrap(),
+        }
+    }
+}
+
+impl<T: ?Sized> Weak<T> {
+    fn inner(&self) -> Option<WeakInner<'_>> {
+        if self.ptr.as_ptr() as *mut u8 as usize == usize::MAX {
+            return None;
+        }
+
+        // the allocation lives as long as a weak reference does, the data
+        // may be dropped already so only the counters get a reference
+        let ptr = self.ptr.as_ptr();
+        unsafe {
+            Some(WeakInner {
+                strong: &*ptr::addr_of!((*ptr).strong),
+                weak: &*ptr::addr_of!((*ptr).weak),
+            })
+        }
+    }
+
+    /// Returns an `Rc` if the data was not dropped yet
+    pub fn upgrade(&self) -> Option<Rc<T>> {
+        let inner = self.inner()?;
+        if inner.strong.get() == 0 {
+            return None;
+        }
+
+        inner.inc_strong();
+        Some(Rc {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.inner().map(|inner| inner.strong.get()).unwrap_or(0)
+    }
+
+    /// Number of `Weak` pointers, 0 once the data was dropped
+    pub fn weak_count(&self) -> usize {
+        match self.inner() {
+            Some(inner) if inner.strong.get() > 0 => inner.weak.get() - 1,
+            _ => 0,
+        }
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+impl<T: ?Sized> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.inner() {
+            inner.inc_weak();
+        }
+
+        Weak { ptr: self.ptr }
+    }
+}
+
+impl<T: ?Sized> Drop for Weak<T> {
+    fn drop(&mut self) {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        inner.dec_weak();
+        if inner.weak.get() != 0 {
+            return;
+        }
+
+        // the data was dropped by the last `Rc`, only free the memory
+        unsafe {
+            let data = ptr::addr_of!((*self.ptr.as_ptr()).data);
+            let layout = inner_layout::<RcInner<()>>(data_layout(data));
+            alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout);
+        }
+    }
+}
+
+#[cfg(test)]
+struct DropCounter(Rc<Cell<usize>>);
+
+#[cfg(test)]
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+fn rc_test_weak() {
+    let drops = Rc::new(Cell::new(0));
+    let rc = Rc::new(DropCounter(drops.clone()));
+    let weak = Rc::downgrade(&rc);
+    let weak2 = weak.clone();
+    assert_eq!((Rc::strong_count(&rc), Rc::weak_count(&rc)), (1, 2));
+
+    let rc2 = weak.upgrade().unwrap();
+    assert_eq!(weak.strong_count(), 2);
+    drop(rc);
+    drop(rc2);
+
+    // data is gone as soon as the last strong reference is
+    assert_eq!(drops.get(), 1);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(weak2.weak_count(), 0);
+    drop(weak);
+    drop(weak2);
+    assert_eq!(drops.get(), 1);
+
+    let empty: Weak<u8> = Weak::new();
+    assert!(empty.upgrade().is_none());
+    assert_eq!(empty.clone().weak_count(), 0);
+}
+
+#[test]
+fn rc_test_ownership() {
+    let mut rc = Rc::new(5);
+    *Rc::get_mut(&mut rc).unwrap() += 1;
+    let mut other = rc.clone();
+    assert!(Rc::get_mut(&mut rc).is_none());
+
+    // clones the data, both now own a separate value
+    *Rc::make_mut(&mut rc) *= 2;
+    *Rc::make_mut(&mut other) += 1;
+    assert_eq!((*rc, *other), (12, 7));
+    assert!(!Rc::ptr_eq(&rc, &other));
+
+    // weak references are cut loose instead of cloning
+    let weak = Rc::downgrade(&rc);
+    assert!(Rc::get_mut(&mut rc).is_none());
+    *Rc::make_mut(&mut rc) += 1;
+    assert_eq!(*rc, 13);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(Rc::weak_count(&rc), 0);
+
+    let shared = rc.clone();
+    let rc = Rc::try_unwrap(rc).unwrap_err();
+    assert_eq!(Rc::into_inner(shared), None);
+    assert_eq!(Rc::unwrap_or_clone(rc), 13);
+
+    let rc = Rc::new(String::from("raw"));
+    let ptr = Rc::into_raw(rc);
+    unsafe {
+        Rc::increment_strong_count(ptr);
+        let rc = Rc::from_raw(ptr);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        Rc::decrement_strong_count(ptr);
+        assert_eq!(Rc::try_unwrap(rc).unwrap(), "raw");
+    }
+}
+
+#[test]
+fn rc_test_unsized() {
+    let slice: Rc<[i32]> = (1..=3).collect();
+    assert_eq!(&*slice, &[1, 2, 3]);
+    assert_eq!(slice, Rc::from(&[1, 2, 3][..]));
+
+    let s: Rc<str> = Rc::from("text");
+    let weak = Rc::downgrade(&s);
+    assert_eq!(&*weak.upgrade().unwrap(), "text");
+    assert_eq!(format!("{} {:?}", s, s), "text \"text\"");
+
+    let drops = Rc::new(Cell::new(0));
+    let any: Rc<dyn std::any::Any> = Rc::from_box(Box::new(DropCounter(drops.clone())));
+    assert!(any.is::<DropCounter>());
+    let weak = Rc::downgrade(&any);
+    drop(any);
+    assert_eq!(drops.get(), 1);
+
+    // the counters and the allocation outlive the data
+    assert!(weak.upgrade().is_none());
+    assert_eq!((weak.strong_count(), weak.weak_count()), (0, 0));
+    drop(weak);
+}
@@ -82,6 +82,68 @@ impl<T> Vec<T> {
         }
     }
 
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    /// Keeps only the elements for which f returns true, preserving order
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        /// Closes the gap of deleted elements, even if f or a drop panics
+        struct Guard<'a, T> {
+            vec: &'a mut Vec<T>,
+            len: usize,
+            processed: usize,
+            deleted: usize,
+        }
+
+        impl<T> Drop for Guard<'_, T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let ptr = self.vec.ptr();
+                    if self.deleted > 0 {
+                        ptr::copy(
+                            ptr.add(self.processed),
+                            ptr.add(self.processed - self.deleted),
+                            self.len - self.processed,
+                        );
+                    }
+                }
+                self.vec.len = self.len - self.deleted;
+            }
+        }
+
+        let len = self.len;
+        // elements are owned by the guard until it is dropped
+        self.len = 0;
+        let mut guard = Guard {
+            vec: self,
+            len,
+            processed: 0,
+            deleted: 0,
+        };
+
+        while guard.processed < len {
+            unsafe {
+                let cur = guard.vec.ptr().add(guard.processed);
+                if !f(&*cur) {
+                    // count it first, so a panicking drop is not dropped again
+                    guard.processed += 1;
+                    guard.deleted += 1;
+                    ptr::drop_in_place(cur);
+                } else {
+                    if guard.deleted > 0 {
+                        let dst = guard.vec.ptr().add(guard.processed - guard.deleted);
+                        ptr::copy_nonoverlapping(cur, dst, 1);
+                    }
+                    guard.processed += 1;
+                }
+            }
+        }
+    }
+
     pub fn remove(&mut self, index: usize) -> T {
         assert!(
             index <= self.len,
@@ -101,9 +163,15 @@ impl<T> Vec<T> {
     }
 }
 
+impl<T> Default for Vec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Drop for Vec<T> {
     fn drop(&mut self) {
-        while let Some(_) = self.pop() {}
+        while self.pop().is_some() {}
     }
 }
 
@@ -185,10 +253,16 @@ impl<'a, T> DoubleEndedIterator for _Drain<'a, T> {
     }
 }
 
+impl<T> Drop for _Drain<'_, T> {
+    fn drop(&mut self) {
+        for _ in &mut *self {}
+    }
+}
+
 impl<T> Vec<T> {
-    pub fn drain(&mut self) -> _Drain<T> {
+    pub fn drain(&mut self) -> _Drain<'_, T> {
         unsafe {
-            let iter = RawIter::new(&self);
+            let iter = RawIter::new(self);
             // preventing reading into freed memory
             self.len = 0;
 
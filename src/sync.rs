@@ -1,2 +1,20 @@
 pub mod arc;
+pub mod atomic_arc;
+pub mod atomic_queue;
+pub mod atomic_stack;
+pub mod barrier;
+pub mod clh_lock;
+pub mod condvar;
+pub mod epoch;
+mod futex;
+pub mod lazy_lock;
+pub mod mpsc;
 pub mod mutex;
+pub mod nonpoison;
+pub mod once;
+pub mod once_lock;
+pub mod poison;
+pub mod rwlock;
+pub mod semaphore;
+pub mod spsc;
+pub mod ticket_lock;
@@ -1,24 +1,68 @@
 #![allow(unused)]
 use std::{
+    alloc::{self, Layout},
+    borrow::Borrow,
+    cmp, fmt,
+    hash::{Hash, Hasher},
     marker::PhantomData,
+    mem::{self, ManuallyDrop},
     ops::Deref,
-    ptr::NonNull,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr::{self, NonNull},
+};
+
+// the loom tests below swap in atomics they can model check
+#[cfg(loom)]
+use loom::{
+    hint,
+    sync::atomic::{self, AtomicUsize, Ordering},
+};
+#[cfg(not(loom))]
+use std::{
+    hint,
     sync::atomic::{self, AtomicUsize, Ordering},
 };
 
-pub struct Arc<T> {
+use crate::collection::raw::refcount::{data_layout, data_offset, inner_layout, set_data_ptr};
+
+/// Refcounts past this are treated as a leak, we abort instead of overflowing
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+pub struct Arc<T: ?Sized> {
     ptr: NonNull<ArcInner<T>>,
     phantom: PhantomData<ArcInner<T>>,
 }
-pub struct ArcInner<T> {
-    rc: atomic::AtomicUsize,
+
+/// Non owning reference to the data of an `Arc`
+///
+/// Does not keep the data alive, only the allocation
+pub struct Weak<T: ?Sized> {
+    // dangling (usize::MAX) for `Weak::new`, no allocation behind it
+    ptr: NonNull<ArcInner<T>>,
+}
+
+// repr(C) fixes the data behind the counters, so the offset of the data
+// only depends on its alignment, even for unsized T
+#[repr(C)]
+pub struct ArcInner<T: ?Sized> {
+    strong: atomic::AtomicUsize,
+    // weak references + 1, all strong references together hold one weak
+    weak: atomic::AtomicUsize,
     data: T,
 }
 
+/// The counters of an `ArcInner`, reachable through a `Weak` even after
+/// the data was dropped
+struct WeakInner<'a> {
+    strong: &'a AtomicUsize,
+    weak: &'a AtomicUsize,
+}
+
 impl<T> Arc<T> {
     pub fn new(data: T) -> Arc<T> {
         let boxed = Box::new(ArcInner {
-            rc: AtomicUsize::new(1),
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
             data,
         });
 
@@ -27,12 +71,385 @@ impl<T> Arc<T> {
             phantom: PhantomData,
         }
     }
+
+    /// Mutable access to the data, clones it into a new allocation first
+    /// if other `Arc`s point to it
+    ///
+    /// `Weak`s pointing to a uniquely owned data are disassociated instead
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        // taking the strong count to 0 keeps `Weak`s from upgrading
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // other `Arc`s exist, clone the data
+            *this = Arc::new((**this).clone());
+        } else if this.inner().weak.load(Ordering::Relaxed) != 1 {
+            // only `Weak`s are left, move the data out and let them see a
+            // dropped value. the strong count is already 0, so release the
+            // weak reference of the strong ones instead of dropping `this`
+            let _weak = Weak { ptr: this.ptr };
+            unsafe {
+                let data = ptr::read(&this.inner().data);
+                ptr::write(this, Arc::new(data));
+            }
+        } else {
+            // we were the only reference, put the count back
+            this.inner().strong.store(1, Ordering::Release);
+        }
+
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+
+    /// Returns the data if this is the only `Arc`, otherwise gives it back
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
+        }
+
+        atomic::fence(Ordering::Acquire);
+
+        let this = ManuallyDrop::new(this);
+        let data = unsafe { ptr::read(&this.inner().data) };
+        drop(Weak { ptr: this.ptr });
+        Ok(data)
+    }
+
+    /// Drops this `Arc`, returning the data if it was the last one
+    ///
+    /// Unlike `try_unwrap` exactly one of racing callers gets the data
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = ManuallyDrop::new(this);
+
+        if this.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+
+        atomic::fence(Ordering::Acquire);
+
+        let data = unsafe { ptr::read(&this.inner().data) };
+        drop(Weak { ptr: this.ptr });
+        Some(data)
+    }
+
+    /// Returns the data if this is the only `Arc`, otherwise a clone of it
+    pub fn unwrap_or_clone(this: Self) -> T
+    where
+        T: Clone,
+    {
+        Arc::try_unwrap(this).unwrap_or_else(|arc| (*arc).clone())
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    fn inner(&self) -> &ArcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Allocates an `ArcInner` for data with the given layout and sets up
+    /// the counters, the data is left uninitialized
+    ///
+    /// mem_to_inner turns the allocation into a (possibly fat) pointer
+    unsafe fn allocate_for_layout(
+        data: Layout,
+        mem_to_inner: impl FnOnce(*mut u8) -> *mut ArcInner<T>,
+    ) -> *mut ArcInner<T> {
+        let layout = inner_layout::<ArcInner<()>>(data);
+        let mem = alloc::alloc(layout);
+        if mem.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        let inner = mem_to_inner(mem);
+        ptr::addr_of_mut!((*inner).strong).write(AtomicUsize::new(1));
+        ptr::addr_of_mut!((*inner).weak).write(AtomicUsize::new(1));
+        inner
+    }
+
+    /// Moves the content of a box into a new `Arc`
+    ///
+    /// Works for unsized content, e.g. `Box<dyn Trait>` into `Arc<dyn Trait>`
+    pub fn from_box(boxed: Box<T>) -> Arc<T> {
+        unsafe {
+            let data_layout = Layout::for_value(&*boxed);
+            let bptr = Box::into_raw(boxed);
+            let inner = Arc::allocate_for_layout(data_layout, |mem| {
+                set_data_ptr(bptr as *mut ArcInner<T>, mem)
+            });
+
+            // move the value over, then free the box without dropping it
+            ptr::copy_nonoverlapping(
+                bptr as *const u8,
+                ptr::addr_of_mut!((*inner).data) as *mut u8,
+                data_layout.size(),
+            );
+            if data_layout.size() != 0 {
+                alloc::dealloc(bptr as *mut u8, data_layout);
+            }
+
+            Arc {
+                ptr: NonNull::new_unchecked(inner),
+                phantom: PhantomData,
+            }
+        }
+    }
+
+    /// Creates a new `Weak` pointer to this allocation
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        let inner = this.inner();
+        let mut weak = inner.weak.load(Ordering::Relaxed);
+
+        loop {
+            // the weak count is locked by `is_unique`, wait for it
+            if weak == usize::MAX {
+                hint::spin_loop();
+                weak = inner.weak.load(Ordering::Relaxed);
+                continue;
+            }
+
+            if weak >= MAX_REFCOUNT {
+                std::process::abort();
+            }
+
+            // acquire pairs with the release in `is_unique`
+            match inner.weak.compare_exchange_weak(
+                weak,
+                weak + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Weak { ptr: this.ptr },
+                Err(old) => weak = old,
+            }
+        }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+    /// Number of `Weak` pointers, without the one held by the strong ones
+    pub fn weak_count(this: &Self) -> usize {
+        let weak = this.inner().weak.load(Ordering::Acquire);
+        // locked means there was no `Weak`
+        if weak == usize::MAX {
+            0
+        } else {
+            weak - 1
+        }
+    }
+
+    /// True if this is the only `Arc` and there is no `Weak`
+    fn is_unique(&mut self) -> bool {
+        // lock the weak count, so no `Weak` can show up
+        // while we look at the strong count
+        if self
+            .inner()
+            .weak
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            // acquire pairs with the release in `Arc::drop`,
+            // writes through other dropped `Arc`s are visible
+            let unique = self.inner().strong.load(Ordering::Acquire) == 1;
+            self.inner().weak.store(1, Ordering::Release);
+            unique
+        } else {
+            false
+        }
+    }
+
+    /// Mutable access to the data if no other `Arc` or `Weak` points to it
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            unsafe { Some(&mut (*this.ptr.as_ptr()).data) }
+        } else {
+            None
+        }
+    }
+
+    /// True if both point to the same allocation
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).data) }
+    }
+
+    /// Consumes the `Arc` without touching the counts,
+    /// use `from_raw` to get it back
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Arc::as_ptr(&this);
+        mem::forget(this);
+        ptr
+    }
+
+    /// Rebuilds an `Arc` from `into_raw`
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Arc::<U>::into_raw`, where U has the same size
+    /// and alignment as T (or is T), and each call takes over one strong
+    /// reference
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let offset = data_offset::<ArcInner<()>>(mem::align_of_val(&*ptr));
+        let inner = ptr.byte_sub(offset) as *mut ArcInner<T>;
+
+        Arc {
+            ptr: NonNull::new_unchecked(inner),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Adds a strong reference to the allocation of ptr
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Arc::<T>::into_raw`, and the allocation has
+    /// to be alive
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let arc = ManuallyDrop::new(Arc::from_raw(ptr));
+        let _clone: ManuallyDrop<_> = arc.clone();
+    }
+
+    /// Drops a strong reference to the allocation of ptr
+    ///
+    /// # Safety
+    ///
+    /// ptr has to come from `Arc::<T>::into_raw`, and the strong reference
+    /// given up has to be owned by the caller
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Arc::from_raw(ptr));
+    }
+}
+
+impl<T> Arc<[T]> {
+    /// Allocates an `ArcInner<[T]>` with room for len elements
+    unsafe fn allocate_for_slice(len: usize) -> *mut ArcInner<[T]> {
+        Arc::allocate_for_layout(Layout::array::<T>(len).unwrap(), |mem| {
+            ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut ArcInner<[T]>
+        })
+    }
+
+    /// Builds the slice from an iterator yielding exactly len elements
+    ///
+    /// Elements already written are dropped and the memory freed
+    /// if the iterator panics
+    unsafe fn from_iter_exact(iter: impl Iterator<Item = T>, len: usize) -> Arc<[T]> {
+        struct Guard<T> {
+            mem: *mut u8,
+            elems: *mut T,
+            layout: Layout,
+            n_elems: usize,
+        }
+
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.elems, self.n_elems));
+                    alloc::dealloc(self.mem, self.layout);
+                }
+            }
+        }
+
+        let inner = Arc::allocate_for_slice(len);
+        let elems = ptr::addr_of_mut!((*inner).data) as *mut T;
+        let mut guard = Guard {
+            mem: inner as *mut u8,
+            elems,
+            layout: inner_layout::<ArcInner<()>>(Layout::array::<T>(len).unwrap()),
+            n_elems: 0,
+        };
+
+        for (idx, el) in iter.enumerate() {
+            assert!(idx < len, "iterator yielded more than len elements");
+            ptr::write(elems.add(idx), el);
+            guard.n_elems += 1;
+        }
+        assert_eq!(
+            guard.n_elems, len,
+            "iterator yielded less than len elements"
+        );
+
+        mem::forget(guard);
+        Arc {
+            ptr: NonNull::new_unchecked(inner),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Copies the elements of slice, without running any of their code
+    unsafe fn copy_from_slice(slice: &[T]) -> Arc<[T]> {
+        let inner = Arc::allocate_for_slice(slice.len());
+        ptr::copy_nonoverlapping(
+            slice.as_ptr(),
+            ptr::addr_of_mut!((*inner).data) as *mut T,
+            slice.len(),
+        );
+        Arc {
+            ptr: NonNull::new_unchecked(inner),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    fn from(slice: &[T]) -> Self {
+        unsafe { Arc::from_iter_exact(slice.iter().cloned(), slice.len()) }
+    }
 }
 
-unsafe impl<T: Sync + Send> Send for Arc<T> {}
-unsafe impl<T: Sync + Send> Sync for Arc<T> {}
+impl<T> From<std::vec::Vec<T>> for Arc<[T]> {
+    fn from(vec: std::vec::Vec<T>) -> Self {
+        unsafe {
+            let arc = Arc::copy_from_slice(&vec);
+            // the elements were moved, only free the buffer
+            let mut vec = ManuallyDrop::new(vec);
+            vec.set_len(0);
+            ManuallyDrop::drop(&mut vec);
+            arc
+        }
+    }
+}
+
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().collect::<std::vec::Vec<T>>().into()
+    }
+}
 
-impl<T> Deref for Arc<T> {
+impl From<&str> for Arc<str> {
+    fn from(s: &str) -> Self {
+        let bytes: Arc<[u8]> = Arc::from(s.as_bytes());
+        // str has the same layout as [u8]
+        unsafe { Arc::from_raw(Arc::into_raw(bytes) as *const str) }
+    }
+}
+
+impl From<String> for Arc<str> {
+    fn from(s: String) -> Self {
+        Arc::from(&s[..])
+    }
+}
+
+unsafe impl<T: ?Sized + Sync + Send> Send for Arc<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for Arc<T> {}
+
+unsafe impl<T: ?Sized + Sync + Send> Send for Weak<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for Weak<T> {}
+
+impl<T: ?Sized> Deref for Arc<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -41,13 +458,13 @@ impl<T> Deref for Arc<T> {
     }
 }
 
-impl<T> Clone for Arc<T> {
+impl<T: ?Sized> Clone for Arc<T> {
     fn clone(&self) -> Self {
         let inner = unsafe { self.ptr.as_ref() };
 
-        let old_rc = inner.rc.fetch_add(1, Ordering::Relaxed);
+        let old_rc = inner.strong.fetch_add(1, Ordering::Relaxed);
 
-        if old_rc >= isize::MAX as usize {
+        if old_rc >= MAX_REFCOUNT {
             // panic if rc >= isize max
             std::process::abort();
         }
@@ -59,11 +476,11 @@ impl<T> Clone for Arc<T> {
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T: ?Sized> Drop for Arc<T> {
     fn drop(&mut self) {
         let inner = unsafe { self.ptr.as_ref() };
 
-        if inner.rc.fetch_sub(1, Ordering::Release) != 1 {
+        if inner.strong.fetch_sub(1, Ordering::Release) != 1 {
             // if remained count over 1, return
             return;
         }
@@ -73,7 +490,742 @@ impl<T> Drop for Arc<T> {
         atomic::fence(Ordering::Acquire);
 
         unsafe {
-            Box::from_raw(self.ptr.as_ptr());
+            ptr::drop_in_place(&mut (*self.ptr.as_ptr()).data);
+        }
+
+        // release the weak reference held by the strong ones,
+        // frees the allocation if no `Weak` is left
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for Arc<T> {
+    /// Formats the address of the data
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&Arc::as_ptr(self), f)
+    }
+}
+
+impl<T: Default> Default for Arc<T> {
+    fn default() -> Self {
+        Arc::new(T::default())
+    }
+}
+
+impl Default for Arc<str> {
+    fn default() -> Self {
+        Arc::from("")
+    }
+}
+
+impl<T> Default for Arc<[T]> {
+    fn default() -> Self {
+        Arc::from(std::vec::Vec::new())
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Arc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Arc<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for Arc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for Arc<T> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Arc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Arc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Arc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> From<T> for Arc<T> {
+    fn from(data: T) -> Self {
+        Arc::new(data)
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Arc<T> {
+    fn from(boxed: Box<T>) -> Self {
+        Arc::from_box(boxed)
+    }
+}
+
+// the data never moves, only the pointer to it
+impl<T: ?Sized> Unpin for Arc<T> {}
+
+impl<T: ?Sized + RefUnwindSafe> UnwindSafe for Arc<T> {}
+
+impl<T> Weak<T> {
+    /// Creates a `Weak` without an allocation, it never upgrades
+    pub fn new() -> Weak<T> {
+        Weak {
+            ptr: NonNull::new(usize::MAX as *mut ArcInner<T>).unwrap(),
         }
     }
 }
+
+impl<T: ?Sized> Weak<T> {
+    fn is_dangling(&self) -> bool {
+        self.ptr.as_ptr() as *mut u8 as usize == usize::MAX
+    }
+
+    fn inner(&self) -> Option<WeakInner<'_>> {
+        if self.is_dangling() {
+            return None;
+        }
+
+        // the allocation lives as long as a weak reference does, the data
+        // may be dropped already so only the counters get a reference
+        let ptr = self.ptr.as_ptr();
+        unsafe {
+            Some(WeakInner {
+                strong: &*ptr::addr_of!((*ptr).strong),
+                weak: &*ptr::addr_of!((*ptr).weak),
+            })
+        }
+    }
+
+    /// Returns an `Arc` if the data was not dropped yet
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        let inner = self.inner()?;
+        let mut strong = inner.strong.load(Ordering::Relaxed);
+
+        loop {
+            // once the strong count hits zero the data is gone for good
+            if strong == 0 {
+                return None;
+            }
+
+            if strong >= MAX_REFCOUNT {
+                std::process::abort();
+            }
+
+            // acquire pairs with the release in `Arc::drop`,
+            // so we see every write done before the count went down
+            match inner.strong.compare_exchange_weak(
+                strong,
+                strong + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(Arc {
+                        ptr: self.ptr,
+                        phantom: PhantomData,
+                    })
+                }
+                Err(old) => strong = old,
+            }
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.inner()
+            .map(|inner| inner.strong.load(Ordering::Acquire))
+            .unwrap_or(0)
+    }
+
+    /// Number of `Weak` pointers, 0 once the data was dropped
+    pub fn weak_count(&self) -> usize {
+        match self.inner() {
+            Some(inner) => {
+                let weak = inner.weak.load(Ordering::Acquire);
+                let strong = inner.strong.load(Ordering::Acquire);
+                if strong == 0 {
+                    0
+                } else {
+                    weak - 1
+                }
+            }
+            None => 0,
+        }
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+impl<T: ?Sized> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.inner() {
+            let old_weak = inner.weak.fetch_add(1, Ordering::Relaxed);
+
+            if old_weak >= MAX_REFCOUNT {
+                std::process::abort();
+            }
+        }
+
+        Weak { ptr: self.ptr }
+    }
+}
+
+impl<T: ?Sized> Drop for Weak<T> {
+    fn drop(&mut self) {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        if inner.weak.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+
+        // synchronizes-with every release-decrement before,
+        // nobody touches the allocation after this
+        atomic::fence(Ordering::Acquire);
+
+        // the data was dropped by the last `Arc`, only free the memory
+        unsafe {
+            let data = ptr::addr_of!((*self.ptr.as_ptr()).data);
+            let layout = inner_layout::<ArcInner<()>>(data_layout(data));
+            alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout);
+        }
+    }
+}
+
+/// Counts its drops, shared by the tests of the sync module
+///
+/// The count is a std atomic so it stays the same type under loom
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct DropCounter(pub(crate) Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(test)]
+impl DropCounter {
+    /// A fresh count for the `DropCounter`s of a test
+    pub(crate) fn count() -> Arc<std::sync::atomic::AtomicUsize> {
+        Arc::new(std::sync::atomic::AtomicUsize::new(0))
+    }
+}
+
+#[cfg(test)]
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn arc_test_weak() {
+    let drops = DropCounter::count();
+    let arc = Arc::new(DropCounter(drops.clone()));
+    let weak = Arc::downgrade(&arc);
+    let weak2 = weak.clone();
+
+    assert_eq!(Arc::strong_count(&arc), 1);
+    assert_eq!(Arc::weak_count(&arc), 2);
+
+    let arc2 = weak.upgrade().unwrap();
+    assert_eq!(Arc::strong_count(&arc), 2);
+    drop(arc);
+    drop(arc2);
+
+    // data is gone as soon as the last strong reference is
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(weak.strong_count(), 0);
+    assert_eq!(weak2.weak_count(), 0);
+    drop(weak);
+    drop(weak2);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    let empty: Weak<u32> = Weak::new();
+    assert!(empty.upgrade().is_none());
+    assert_eq!(empty.clone().strong_count(), 0);
+}
+
+#[test]
+fn arc_test_parent_pointer_tree() {
+    use std::sync::Mutex;
+
+    struct Node {
+        parent: Weak<Node>,
+        children: Mutex<std::vec::Vec<Arc<Node>>>,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let root = Arc::new(Node {
+        parent: Weak::new(),
+        children: Mutex::new(std::vec::Vec::new()),
+        drops: drops.clone(),
+    });
+    for _ in 0..3 {
+        let child = Arc::new(Node {
+            parent: Arc::downgrade(&root),
+            children: Mutex::new(std::vec::Vec::new()),
+            drops: drops.clone(),
+        });
+        root.children.lock().unwrap().push(child);
+    }
+
+    let child = root.children.lock().unwrap()[1].clone();
+    assert!(child.parent.upgrade().is_some());
+    assert_eq!(Arc::weak_count(&root), 3);
+
+    // no cycle of strong references, so everything is freed
+    drop(root);
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+    assert!(child.parent.upgrade().is_none());
+    drop(child);
+    assert_eq!(drops.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn arc_test_weak_threaded() {
+    use std::thread;
+
+    // race upgrades against the last strong reference going away,
+    // the data must be dropped exactly once and never used after
+    for _ in 0..200 {
+        let drops = DropCounter::count();
+        let arc = Arc::new((DropCounter(drops.clone()), 42));
+
+        let handles: std::vec::Vec<_> = (0..4)
+            .map(|_| {
+                let weak = Arc::downgrade(&arc);
+                thread::spawn(move || {
+                    let mut upgraded = 0;
+                    for _ in 0..100 {
+                        if let Some(arc) = weak.upgrade() {
+                            assert_eq!(arc.1, 42);
+                            let weak2 = Arc::downgrade(&arc);
+                            drop(arc);
+                            drop(weak2);
+                            upgraded += 1;
+                        }
+                    }
+                    upgraded
+                })
+            })
+            .collect();
+
+        let clones: std::vec::Vec<_> = (0..4).map(|_| arc.clone()).collect();
+        drop(arc);
+        drop(clones);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[test]
+fn arc_test_get_mut() {
+    let mut arc = Arc::new(3);
+    *Arc::get_mut(&mut arc).unwrap() = 4;
+
+    let other = arc.clone();
+    assert!(Arc::get_mut(&mut arc).is_none());
+    drop(other);
+
+    let weak = Arc::downgrade(&arc);
+    assert!(Arc::get_mut(&mut arc).is_none());
+    drop(weak);
+    assert_eq!(Arc::get_mut(&mut arc), Some(&mut 4));
+}
+
+#[test]
+fn arc_test_make_mut() {
+    let mut data = Arc::new(5);
+    *Arc::make_mut(&mut data) += 1;
+    let mut other = data.clone();
+
+    // clones the data, both now own a separate value
+    *Arc::make_mut(&mut data) += 1;
+    *Arc::make_mut(&mut data) *= 2;
+    *Arc::make_mut(&mut other) *= 10;
+    assert_eq!((*data, *other), (14, 60));
+    assert!(!Arc::ptr_eq(&data, &other));
+
+    // weak references are cut loose instead of cloning
+    let weak = Arc::downgrade(&data);
+    *Arc::make_mut(&mut data) += 1;
+    assert_eq!(*data, 15);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(Arc::weak_count(&data), 0);
+}
+
+#[test]
+fn arc_test_unwrap() {
+    let arc = Arc::new(String::from("x"));
+    let other = arc.clone();
+    let arc = Arc::try_unwrap(arc).unwrap_err();
+    assert_eq!(Arc::into_inner(other), None);
+    assert_eq!(Arc::try_unwrap(arc).unwrap(), "x");
+
+    let arc = Arc::new(vec![1, 2]);
+    let other = arc.clone();
+    assert_eq!(Arc::unwrap_or_clone(arc), [1, 2]);
+    assert_eq!(Arc::unwrap_or_clone(other), [1, 2]);
+
+    // the allocation outlives the data while weak references are around
+    let drops = DropCounter::count();
+    let arc = Arc::new(DropCounter(drops.clone()));
+    let weak = Arc::downgrade(&arc);
+    let data = Arc::into_inner(arc).unwrap();
+    assert!(weak.upgrade().is_none());
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    drop(data);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn arc_test_into_inner_threaded() {
+    use std::thread;
+
+    for _ in 0..100 {
+        let arc = Arc::new(String::from("data"));
+        let handles: std::vec::Vec<_> = (0..4)
+            .map(|_| {
+                let arc = arc.clone();
+                thread::spawn(move || Arc::into_inner(arc))
+            })
+            .collect();
+
+        let mut found = Arc::into_inner(arc).into_iter().count();
+        for handle in handles {
+            found += handle.join().unwrap().into_iter().count();
+        }
+        assert_eq!(found, 1);
+    }
+}
+
+#[test]
+fn arc_test_raw() {
+    let arc = Arc::new(String::from("raw"));
+    let ptr = Arc::into_raw(arc);
+
+    unsafe {
+        assert_eq!(*ptr, "raw");
+        Arc::increment_strong_count(ptr);
+        let arc = Arc::from_raw(ptr);
+        assert_eq!(Arc::strong_count(&arc), 2);
+        assert_eq!(Arc::as_ptr(&arc), ptr);
+        Arc::decrement_strong_count(ptr);
+        assert_eq!(Arc::strong_count(&arc), 1);
+        assert_eq!(Arc::try_unwrap(arc).unwrap(), "raw");
+    }
+
+    // works for types aligned past the counters
+    #[repr(align(64))]
+    struct Aligned(u8);
+    let ptr = Arc::into_raw(Arc::new(Aligned(7)));
+    let arc = unsafe { Arc::from_raw(ptr) };
+    assert_eq!(arc.0, 7);
+}
+
+#[test]
+fn arc_test_slice() {
+    let arc: Arc<[i32]> = Arc::from(&[1, 2, 3][..]);
+    assert_eq!(&*arc, &[1, 2, 3]);
+
+    let strings = vec![String::from("a"), String::from("b")];
+    let arc: Arc<[String]> = Arc::from(strings);
+    let weak = Arc::downgrade(&arc);
+    assert_eq!(arc.len(), 2);
+    assert_eq!(weak.upgrade().unwrap()[1], "b");
+    drop(arc);
+    assert!(weak.upgrade().is_none());
+
+    let arc: Arc<[u64]> = (0..100).collect();
+    assert_eq!(arc.iter().sum::<u64>(), 4950);
+    let empty: Arc<[u8]> = std::iter::empty().collect();
+    assert!(empty.is_empty());
+
+    // counters of every element are balanced
+    let counter = Arc::new(());
+    let arc: Arc<[Arc<()>]> = (0..10).map(|_| counter.clone()).collect();
+    let clone: Arc<[Arc<()>]> = Arc::from(&arc[..]);
+    assert_eq!(Arc::strong_count(&counter), 21);
+    drop(arc);
+    drop(clone);
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn arc_test_slice_clone_panic() {
+    use std::panic;
+
+    struct Bomb(Arc<()>, bool);
+
+    impl Clone for Bomb {
+        fn clone(&self) -> Self {
+            assert!(!self.1, "boom");
+            Bomb(self.0.clone(), self.1)
+        }
+    }
+
+    let counter = Arc::new(());
+    let bombs: std::vec::Vec<_> = (0..5).map(|i| Bomb(counter.clone(), i == 3)).collect();
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _arc: Arc<[Bomb]> = Arc::from(&bombs[..]);
+    }));
+    assert!(result.is_err());
+    // the three clones made before the panic were dropped
+    assert_eq!(Arc::strong_count(&counter), 6);
+}
+
+#[test]
+fn arc_test_str() {
+    let arc: Arc<str> = Arc::from("hello");
+    let other = arc.clone();
+    assert_eq!(&*other, "hello");
+    assert!(Arc::ptr_eq(&arc, &other));
+
+    let arc: Arc<str> = Arc::from(String::from("world"));
+    let ptr = Arc::into_raw(arc);
+    let arc = unsafe { Arc::from_raw(ptr) };
+    assert_eq!(&*arc, "world");
+}
+
+#[test]
+fn arc_test_dyn() {
+    use std::fmt::Display;
+
+    trait Shape: Send + Sync {
+        fn area(&self) -> f64;
+    }
+
+    struct Square(f64, Arc<AtomicUsize>);
+
+    impl Shape for Square {
+        fn area(&self) -> f64 {
+            self.0 * self.0
+        }
+    }
+
+    impl Drop for Square {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let shape: Arc<dyn Shape> = Arc::from_box(Box::new(Square(3.0, drops.clone())));
+    let weak = Arc::downgrade(&shape);
+
+    let handle = {
+        let shape = shape.clone();
+        std::thread::spawn(move || shape.area())
+    };
+    assert_eq!(handle.join().unwrap(), 9.0);
+    assert_eq!(weak.upgrade().unwrap().area(), 9.0);
+
+    drop(shape);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(weak.upgrade().is_none());
+
+    let text: Arc<dyn Display + Send + Sync> = Arc::from_box(Box::new(42));
+    assert_eq!(text.to_string(), "42");
+
+    // zero sized content
+    let unit: Arc<dyn Send + Sync> = Arc::from_box(Box::new(()));
+    drop(unit);
+}
+
+#[test]
+fn arc_test_traits() {
+    use std::collections::{BTreeSet, HashMap};
+
+    let arc = Arc::new(5);
+    assert_eq!(format!("{:?} {}", arc, arc), "5 5");
+    assert_eq!(format!("{:p}", arc), format!("{:p}", Arc::as_ptr(&arc)));
+    assert_eq!(format!("{:?}", Arc::downgrade(&arc)), "(Weak)");
+
+    assert_eq!(*Arc::<u32>::default(), 0);
+    assert_eq!(&*Arc::<str>::default(), "");
+    assert!(Arc::<[u8]>::default().is_empty());
+
+    assert_eq!(Arc::new(3), Arc::from(3));
+    assert!(Arc::new(1) < Arc::new(2));
+    assert_eq!(Arc::new("b").cmp(&Arc::new("a")), cmp::Ordering::Greater);
+
+    // lookups by the borrowed content, like std::sync::Arc
+    let mut map: HashMap<Arc<str>, usize> = HashMap::new();
+    map.insert(Arc::from("one"), 1);
+    map.insert(Arc::from(String::from("two")), 2);
+    assert_eq!(map.get("two"), Some(&2));
+
+    let set: BTreeSet<Arc<i32>> = [3, 1, 2].into_iter().map(Arc::from).collect();
+    assert!(set.contains(&1));
+    assert_eq!(
+        set.iter().map(|x| **x).collect::<std::vec::Vec<_>>(),
+        [1, 2, 3]
+    );
+
+    let boxed: Box<[u8]> = Box::new([1, 2, 3]);
+    let arc: Arc<[u8]> = Arc::from(boxed);
+    let slice: &[u8] = arc.as_ref();
+    assert_eq!(slice, &[1, 2, 3]);
+
+    fn assert_traits<T: Unpin + UnwindSafe + Send + Sync>(_: &T) {}
+    assert_traits(&arc);
+}
+
+/// Value whose reads and writes loom checks for data races, so a test
+/// fails if a count lets the data be dropped or mutated too early
+#[cfg(loom)]
+struct LoomValue(loom::cell::UnsafeCell<usize>);
+
+#[cfg(loom)]
+unsafe impl Sync for LoomValue {}
+
+#[cfg(loom)]
+impl LoomValue {
+    fn new(value: usize) -> Self {
+        LoomValue(loom::cell::UnsafeCell::new(value))
+    }
+
+    fn get(&self) -> usize {
+        self.0.with(|ptr| unsafe { *ptr })
+    }
+
+    fn set(&mut self, value: usize) {
+        self.0.with_mut(|ptr| unsafe { *ptr = value })
+    }
+}
+
+#[cfg(loom)]
+impl Clone for LoomValue {
+    fn clone(&self) -> Self {
+        LoomValue::new(self.get())
+    }
+}
+
+#[cfg(loom)]
+impl Drop for LoomValue {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
+
+// run with RUSTFLAGS="--cfg loom" cargo test --release --lib arc_test_loom
+
+#[cfg(loom)]
+#[test]
+fn arc_test_loom_clone_drop() {
+    use loom::thread;
+
+    loom::model(|| {
+        let arc = Arc::new(LoomValue::new(1));
+        let other = arc.clone();
+        let handle = thread::spawn(move || assert_eq!(other.get(), 1));
+        assert_eq!(arc.get(), 1);
+        drop(arc);
+        handle.join().unwrap();
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn arc_test_loom_upgrade_last_drop() {
+    use loom::thread;
+
+    loom::model(|| {
+        let arc = Arc::new(LoomValue::new(1));
+        let weak = Arc::downgrade(&arc);
+        let handle = thread::spawn(move || {
+            if let Some(arc) = weak.upgrade() {
+                assert_eq!(arc.get(), 1);
+            }
+        });
+        drop(arc);
+        handle.join().unwrap();
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn arc_test_loom_get_mut() {
+    use loom::thread;
+
+    loom::model(|| {
+        let mut arc = Arc::new(LoomValue::new(1));
+        let other = arc.clone();
+        let handle = thread::spawn(move || {
+            let weak = Arc::downgrade(&other);
+            drop(other);
+            if let Some(arc) = weak.upgrade() {
+                assert_eq!(arc.get(), 1);
+            }
+        });
+        if let Some(value) = Arc::get_mut(&mut arc) {
+            value.set(2);
+        }
+        handle.join().unwrap();
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn arc_test_loom_make_mut() {
+    use loom::thread;
+
+    loom::model(|| {
+        let mut arc = Arc::new(LoomValue::new(1));
+        let weak = Arc::downgrade(&arc);
+        let handle = thread::spawn(move || {
+            // either before make_mut, then it copies, or never
+            if let Some(arc) = weak.upgrade() {
+                assert_eq!(arc.get(), 1);
+            }
+        });
+        Arc::make_mut(&mut arc).set(2);
+        assert_eq!(arc.get(), 2);
+        handle.join().unwrap();
+    });
+}
@@ -0,0 +1,288 @@
+use std::{
+    fmt, hint,
+    marker::PhantomData,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use super::{arc::Arc, nonpoison::Mutex};
+
+/// An `Arc` that can be loaded and replaced atomically
+///
+/// Readers never block, writers wait until no reader can still be looking
+/// at the replaced `Arc` before they release it
+///
+/// Reclamation is a small two slot RCU: a reader registers in the slot of
+/// the current epoch while it loads the pointer and takes a strong
+/// reference. A writer swaps the pointer, then flips the epoch twice and
+/// waits for each slot to drain, new readers always go to the other slot
+pub struct AtomicArc<T> {
+    /// owns one strong reference, from `Arc::into_raw`
+    ptr: AtomicPtr<T>,
+    epoch: AtomicUsize,
+    readers: [AtomicUsize; 2],
+    /// serializes the epoch flips of writers
+    writer: Mutex<()>,
+    phantom: PhantomData<Arc<T>>,
+}
+
+impl<T> AtomicArc<T> {
+    pub fn new(arc: Arc<T>) -> Self {
+        AtomicArc {
+            ptr: AtomicPtr::new(Arc::into_raw(arc) as *mut T),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+            phantom: PhantomData,
+        }
+    }
+
+    // every access to ptr, epoch and readers is SeqCst: a reader stores to
+    // its slot and then loads ptr, a writer stores ptr and then loads the
+    // slots, both need to see the store of the other side
+
+    /// Registers a reader, returns the slot to pass to `leave`
+    fn enter(&self) -> usize {
+        loop {
+            let slot = self.epoch.load(Ordering::SeqCst) & 1;
+            self.readers[slot].fetch_add(1, Ordering::SeqCst);
+
+            // the epoch moved on in between, a writer may already wait
+            // for this slot, so register again in the new one
+            if self.epoch.load(Ordering::SeqCst) & 1 == slot {
+                return slot;
+            }
+            self.readers[slot].fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn leave(&self, slot: usize) {
+        self.readers[slot].fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Waits until no reader can hold a pointer loaded before this call
+    fn synchronize(&self) {
+        let _writer = self.writer.lock();
+        for _ in 0..2 {
+            let old = self.epoch.fetch_add(1, Ordering::SeqCst) & 1;
+            while self.readers[old].load(Ordering::SeqCst) != 0 {
+                hint::spin_loop();
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    /// Returns the current `Arc`
+    pub fn load(&self) -> Arc<T> {
+        let slot = self.enter();
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        // a writer replacing ptr waits for us before dropping it
+        let arc = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        self.leave(slot);
+        arc
+    }
+
+    pub fn store(&self, new: Arc<T>) {
+        drop(self.swap(new));
+    }
+
+    /// Replaces the current `Arc` with new, returning the old one
+    pub fn swap(&self, new: Arc<T>) -> Arc<T> {
+        let old = self
+            .ptr
+            .swap(Arc::into_raw(new) as *mut T, Ordering::SeqCst);
+        self.synchronize();
+        unsafe { Arc::from_raw(old) }
+    }
+
+    /// Replaces the current `Arc` with new if it points to the same
+    /// allocation as current
+    ///
+    /// Returns the `Arc` seen before, which is `ptr_eq` to current on
+    /// success. On failure new is dropped
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Arc<T> {
+        let new = Arc::into_raw(new) as *mut T;
+        let slot = self.enter();
+
+        match self.ptr.compare_exchange(
+            Arc::as_ptr(current) as *mut T,
+            new,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(old) => {
+                self.leave(slot);
+                self.synchronize();
+                unsafe { Arc::from_raw(old) }
+            }
+            Err(actual) => {
+                // still registered, so actual can't be released yet
+                let arc = unsafe {
+                    Arc::increment_strong_count(actual);
+                    Arc::from_raw(actual)
+                };
+                self.leave(slot);
+                drop(unsafe { Arc::from_raw(new) });
+                arc
+            }
+        }
+    }
+
+    /// Read, copy, update: replaces the current `Arc` with f(current),
+    /// calling f again if another writer got in between
+    ///
+    /// Returns the replaced `Arc`
+    pub fn rcu<R, F>(&self, mut f: F) -> Arc<T>
+    where
+        F: FnMut(&Arc<T>) -> R,
+        R: Into<Arc<T>>,
+    {
+        let mut cur = self.load();
+        loop {
+            let new = f(&cur).into();
+            let prev = self.compare_and_swap(&cur, new);
+            if Arc::ptr_eq(&prev, &cur) {
+                return prev;
+            }
+            cur = prev;
+        }
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        std::mem::forget(self);
+        unsafe { Arc::from_raw(ptr) }
+    }
+}
+
+impl<T> Drop for AtomicArc<T> {
+    fn drop(&mut self) {
+        // &mut self, no reader is left
+        drop(unsafe { Arc::from_raw(*self.ptr.get_mut()) });
+    }
+}
+
+impl<T> From<Arc<T>> for AtomicArc<T> {
+    fn from(arc: Arc<T>) -> Self {
+        AtomicArc::new(arc)
+    }
+}
+
+impl<T: Default> Default for AtomicArc<T> {
+    fn default() -> Self {
+        AtomicArc::new(Arc::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AtomicArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicArc").field(&self.load()).finish()
+    }
+}
+
+#[test]
+fn atomic_arc_test_ops() {
+    let a = AtomicArc::new(Arc::new(1));
+    assert_eq!(*a.load(), 1);
+
+    a.store(Arc::new(2));
+    let two = a.load();
+    assert_eq!(*a.swap(Arc::new(3)), 2);
+
+    // current is stale, new is dropped and the current value returned
+    let three = a.compare_and_swap(&two, Arc::new(4));
+    assert_eq!(*three, 3);
+    assert!(!Arc::ptr_eq(&three, &two));
+
+    let prev = a.compare_and_swap(&three, Arc::new(5));
+    assert!(Arc::ptr_eq(&prev, &three));
+    drop(three);
+    assert_eq!(*a.rcu(|old| **old * 10), 5);
+    assert_eq!(format!("{:?}", a), "AtomicArc(50)");
+
+    // the replaced values are only referenced by us
+    assert_eq!(Arc::strong_count(&two), 1);
+    assert_eq!(Arc::strong_count(&prev), 1);
+    assert_eq!(Arc::strong_count(&a.into_inner()), 1);
+}
+
+#[test]
+fn atomic_arc_test_stress() {
+    use std::{sync::atomic::AtomicBool, thread};
+
+    struct Config {
+        version: usize,
+        // every value has the same content, so torn or freed
+        // reads show up as a mismatch
+        check: std::vec::Vec<usize>,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Config {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let config = |version| Config {
+        version,
+        check: vec![version; 8],
+        drops: drops.clone(),
+    };
+
+    let shared = Arc::new(AtomicArc::new(Arc::new(config(0))));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: std::vec::Vec<_> = (0..4)
+        .map(|_| {
+            let shared = shared.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let config = shared.load();
+                    assert!(config.check.iter().all(|&x| x == config.version));
+                    // a single writer only moves forward
+                    assert!(config.version >= last);
+                    last = config.version;
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+
+    let updaters: std::vec::Vec<_> = (0..2)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    shared.rcu(|old| Config {
+                        version: old.version,
+                        check: old.check.clone(),
+                        drops: old.drops.clone(),
+                    });
+                }
+            })
+        })
+        .collect();
+
+    for version in 1..=500 {
+        shared.store(Arc::new(config(version * 1000)));
+    }
+
+    for updater in updaters {
+        updater.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    // 1 initial + 500 stores + 400 rcu copies, all but the last freed
+    assert_eq!(drops.load(Ordering::SeqCst), 900);
+    drop(shared);
+    assert_eq!(drops.load(Ordering::SeqCst), 901);
+}
@@ -0,0 +1,264 @@
+use std::{
+    fmt,
+    mem::MaybeUninit,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::epoch;
+
+struct Node<T> {
+    /// uninit in the sentinel, moved out by the pop that makes the node
+    /// the new sentinel
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(value: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// Lock-free FIFO queue, by Michael and Scott
+///
+/// A linked list with a sentinel node in front. `push` links a node
+/// behind the last one and then swings the tail, `pop` swings the head
+/// to the next node, which becomes the sentinel. A thread finding the
+/// tail behind helps to move it on, so nobody waits for a stalled push.
+/// Nodes are freed through `epoch`, see `AtomicStack`
+pub struct AtomicQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+}
+
+// only the pop that moves the head onto a node reads its value, the
+// helping CASes on the tail never touch values, so T: Send is enough
+unsafe impl<T: Send> Send for AtomicQueue<T> {}
+unsafe impl<T: Send> Sync for AtomicQueue<T> {}
+
+impl<T: RefUnwindSafe> UnwindSafe for AtomicQueue<T> {}
+impl<T: RefUnwindSafe> RefUnwindSafe for AtomicQueue<T> {}
+
+impl<T> AtomicQueue<T> {
+    pub fn new() -> Self {
+        let sentinel = Node::new(MaybeUninit::uninit());
+        AtomicQueue {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Node::new(MaybeUninit::new(value));
+        // the tail may be popped and freed while we look at it
+        let _guard = epoch::pin();
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let tail_ref = unsafe { &*tail };
+            let next = tail_ref.next.load(Ordering::Acquire);
+
+            if !next.is_null() {
+                // a push linked its node but did not move the tail yet
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            // release the value to the popping thread
+            if tail_ref
+                .next
+                .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // fails if somebody helped already
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            let next_ref = unsafe { next.as_ref() }?;
+
+            // never let the head pass the tail, the tail would point to
+            // a freed node then
+            let tail = self.tail.load(Ordering::Relaxed);
+            if head == tail {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    // next is the sentinel now, its value is ours
+                    let value = next_ref.value.assume_init_read();
+                    guard.defer_destroy(head);
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    /// True if the queue was empty at some point during the call
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Drop for AtomicQueue<T> {
+    fn drop(&mut self) {
+        // the sentinel has no value, every node after it has one
+        let sentinel = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut cur = sentinel.next.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { node.value.assume_init_drop() };
+            cur = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Default for AtomicQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FromIterator<T> for AtomicQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let queue = Self::new();
+        for value in iter {
+            queue.push(value);
+        }
+        queue
+    }
+}
+
+impl<T> fmt::Debug for AtomicQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicQueue").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn atomic_queue_test_fifo() {
+    use super::arc::DropCounter;
+
+    let queue: AtomicQueue<_> = (0..3).collect();
+    queue.push(3);
+    assert_eq!(queue.pop(), Some(0));
+    assert_eq!(queue.pop(), Some(1));
+    assert!(!queue.is_empty());
+
+    // values left in the queue are dropped with it
+    let drops = DropCounter::count();
+
+    let queue = AtomicQueue::new();
+    for _ in 0..5 {
+        queue.push(DropCounter(drops.clone()));
+    }
+    drop(queue.pop());
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+    drop(queue);
+    assert_eq!(drops.load(Ordering::Relaxed), 5);
+
+    let empty = AtomicQueue::<i32>::default();
+    assert!(empty.is_empty());
+    assert_eq!(empty.pop(), None);
+}
+
+#[test]
+fn atomic_queue_test_threaded() {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize},
+        thread,
+    };
+
+    const PRODUCERS: usize = 3;
+    const PER_PRODUCER: usize = 5000;
+
+    let queue = AtomicQueue::new();
+    let done = AtomicBool::new(false);
+    let popped = AtomicUsize::new(0);
+
+    let seen: std::vec::Vec<std::vec::Vec<(usize, usize)>> = thread::scope(|s| {
+        let producers: std::vec::Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        queue.push((p, i));
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: std::vec::Vec<_> = (0..2)
+            .map(|_| {
+                let (queue, done, popped) = (&queue, &done, &popped);
+                s.spawn(move || {
+                    let mut seen = std::vec::Vec::new();
+                    loop {
+                        // read before popping, so nothing is left once
+                        // the queue is empty after it
+                        let finished = done.load(Ordering::Acquire);
+                        match queue.pop() {
+                            Some(value) => {
+                                seen.push(value);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None if finished => break,
+                            None => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        done.store(true, Ordering::Release);
+        consumers.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // FIFO: what one consumer got from one producer is in push order
+    for seen in &seen {
+        let mut next = [0; PRODUCERS];
+        for &(p, i) in seen {
+            assert!(i >= next[p]);
+            next[p] = i + 1;
+        }
+    }
+
+    let mut all: std::vec::Vec<_> = seen.into_iter().flatten().collect();
+    all.sort_unstable();
+    let expected: std::vec::Vec<_> = (0..PRODUCERS)
+        .flat_map(|p| (0..PER_PRODUCER).map(move |i| (p, i)))
+        .collect();
+    assert_eq!(all, expected);
+    assert_eq!(popped.load(Ordering::Relaxed), PRODUCERS * PER_PRODUCER);
+    assert!(queue.is_empty());
+}
@@ -0,0 +1,185 @@
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    panic::{RefUnwindSafe, UnwindSafe},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::epoch;
+
+struct Node<T> {
+    /// moved out by the pop that unlinks the node
+    value: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+/// Lock-free LIFO stack, Treiber's
+///
+/// `push` and `pop` are a single CAS on the head. Popped nodes are freed
+/// through `epoch`, so a racing pop never reads a freed node, and the
+/// head can't turn into a new node at an old address under its CAS
+pub struct AtomicStack<T> {
+    head: AtomicPtr<Node<T>>,
+}
+
+// a value goes from the pushing thread to the one whose CAS unlinks its
+// node, no two threads ever see it, so sharing the stack only needs Send
+unsafe impl<T: Send> Send for AtomicStack<T> {}
+unsafe impl<T: Send> Sync for AtomicStack<T> {}
+
+impl<T: RefUnwindSafe> UnwindSafe for AtomicStack<T> {}
+impl<T: RefUnwindSafe> RefUnwindSafe for AtomicStack<T> {}
+
+impl<T> AtomicStack<T> {
+    pub const fn new() -> Self {
+        AtomicStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value: ManuallyDrop::new(value),
+            next: ptr::null_mut(),
+        }));
+
+        // the node is ours until the CAS publishes it, no pin needed
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            // release the value and next to the popping thread
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(cur) => head = cur,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            // pinned, so the node is not freed even if popped meanwhile
+            let node = unsafe { head.as_ref() }?;
+            match self.head.compare_exchange_weak(
+                head,
+                node.next,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => unsafe {
+                    // we unlinked it, so the value is ours
+                    let value = ptr::read(&*node.value);
+                    guard.defer_destroy(head);
+                    return Some(value);
+                },
+                Err(cur) => head = cur,
+            }
+        }
+    }
+
+    /// True if the stack was empty at some point during the call
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+}
+
+impl<T> Drop for AtomicStack<T> {
+    fn drop(&mut self) {
+        // nobody else can see the nodes anymore
+        let mut cur = *self.head.get_mut();
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { ManuallyDrop::drop(&mut node.value) };
+            cur = node.next;
+        }
+    }
+}
+
+impl<T> Default for AtomicStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FromIterator<T> for AtomicStack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let stack = Self::new();
+        for value in iter {
+            stack.push(value);
+        }
+        stack
+    }
+}
+
+impl<T> fmt::Debug for AtomicStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicStack").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn atomic_stack_test_lifo() {
+    use super::arc::DropCounter;
+
+    let stack: AtomicStack<_> = (0..4).collect();
+    assert_eq!(stack.pop(), Some(3));
+    stack.push(9);
+    assert_eq!(stack.pop(), Some(9));
+    assert_eq!(stack.pop(), Some(2));
+
+    // values left on the stack are dropped with it
+    let drops = DropCounter::count();
+
+    let stack = AtomicStack::new();
+    for _ in 0..5 {
+        stack.push(DropCounter(drops.clone()));
+    }
+    drop(stack.pop());
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+    drop(stack);
+    assert_eq!(drops.load(Ordering::Relaxed), 5);
+}
+
+#[test]
+fn atomic_stack_test_threaded() {
+    use std::thread;
+
+    const THREADS: usize = 4;
+    const PER_THREAD: usize = 5000;
+
+    let stack = AtomicStack::new();
+
+    // every thread pushes its own values and pops whatever it finds
+    let popped: std::vec::Vec<std::vec::Vec<usize>> = thread::scope(|s| {
+        let handles: std::vec::Vec<_> = (0..THREADS)
+            .map(|t| {
+                let stack = &stack;
+                s.spawn(move || {
+                    let mut popped = std::vec::Vec::new();
+                    for i in 0..PER_THREAD {
+                        stack.push(t * PER_THREAD + i);
+                        if i % 3 != 0 {
+                            popped.extend(stack.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // each value comes out exactly once
+    let mut all: std::vec::Vec<_> = popped.into_iter().flatten().collect();
+    while let Some(value) = stack.pop() {
+        all.push(value);
+    }
+    all.sort_unstable();
+    assert_eq!(all, (0..THREADS * PER_THREAD).collect::<std::vec::Vec<_>>());
+    assert!(stack.is_empty());
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+use super::{condvar::Condvar, mutex::Mutex, poison::PoisonError};
+
+/// Lets n threads wait for each other before going on
+///
+/// `wait` blocks until n threads called it, then all of them return and
+/// the barrier starts over for the next round, a generation
+pub struct Barrier {
+    n: usize,
+    /// arrival and the generation it counts for are one step under the
+    /// lock, so a late thread can't count itself into the next round
+    /// while still waiting for the previous one
+    state: Mutex<BarrierState>,
+    cvar: Condvar,
+}
+
+struct BarrierState {
+    /// threads arrived in the current generation
+    count: usize,
+    /// bumped by the last thread of each generation
+    generation: usize,
+}
+
+/// Returned by `Barrier::wait`, one thread of each generation is the leader
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// A barrier for n threads, with n = 0 every `wait` returns right away
+    pub const fn new(n: usize) -> Self {
+        Barrier {
+            n,
+            state: Mutex::new(BarrierState {
+                count: 0,
+                generation: 0,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    /// Blocks until n threads are waiting, the last one to arrive is the
+    /// leader
+    pub fn wait(&self) -> BarrierWaitResult {
+        // nothing panics under the lock, there is no poison to care about
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let generation = state.generation;
+
+        state.count += 1;
+        if state.count >= self.n {
+            state.count = 0;
+            state.generation = state.generation.wrapping_add(1);
+            self.cvar.notify_all();
+            return BarrierWaitResult(true);
+        }
+
+        let _state = self
+            .cvar
+            .wait_while(state, |state| state.generation == generation)
+            .unwrap_or_else(PoisonError::into_inner);
+        BarrierWaitResult(false)
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for BarrierWaitResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BarrierWaitResult")
+            .field("is_leader", &self.0)
+            .finish()
+    }
+}
+
+#[test]
+fn barrier_test_generations() {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    const THREADS: usize = 6;
+    const ROUNDS: usize = 50;
+
+    let barrier = Barrier::new(THREADS);
+    let slots: std::vec::Vec<_> = (0..THREADS).map(|_| AtomicUsize::new(0)).collect();
+    let leaders = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for me in 0..THREADS {
+            let (barrier, slots, leaders) = (&barrier, &slots, &leaders);
+            s.spawn(move || {
+                for round in 1..=ROUNDS {
+                    slots[me].store(round, Ordering::Relaxed);
+                    if barrier.wait().is_leader() {
+                        leaders.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // everybody finished this round before anyone goes on
+                    for slot in slots {
+                        assert!(slot.load(Ordering::Relaxed) >= round);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(leaders.load(Ordering::Relaxed), ROUNDS);
+
+    let single = Barrier::new(1);
+    assert!(single.wait().is_leader() && single.wait().is_leader());
+    assert!(Barrier::new(0).wait().is_leader());
+}
+
+#[test]
+fn barrier_test_more_threads_than_n() {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    const N: usize = 3;
+    const GENERATIONS: usize = 600;
+
+    // 3 * N threads on a barrier for N. They share the waits, a thread
+    // with fixed rounds could be left over at the end with nobody to
+    // fill its generation
+    let barrier = Barrier::new(N);
+    let tickets = AtomicUsize::new(GENERATIONS * N);
+    let entered = AtomicUsize::new(0);
+    let returned = AtomicUsize::new(0);
+    let leaders = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..3 * N {
+            s.spawn(|| {
+                while tickets
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |t| t.checked_sub(1))
+                    .is_ok()
+                {
+                    entered.fetch_add(1, Ordering::SeqCst);
+                    if barrier.wait().is_leader() {
+                        leaders.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // every thread that left belongs to a full generation,
+                    // nobody gets through with a round that isn't complete
+                    let returned = returned.fetch_add(1, Ordering::SeqCst) + 1;
+                    assert!(returned <= entered.load(Ordering::SeqCst) / N * N);
+                }
+            });
+        }
+    });
+    assert_eq!(leaders.load(Ordering::Relaxed), GENERATIONS);
+}
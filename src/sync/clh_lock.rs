@@ -0,0 +1,165 @@
+use std::{
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use super::mutex::{spin_while, Mutex, MutexGuard, RawLock};
+
+/// `Mutex` queueing its waiters, each spinning on its own cache line
+pub type ClhLock<T> = Mutex<T, RawClhLock>;
+
+pub type ClhLockGuard<'a, T> = MutexGuard<'a, T, RawClhLock>;
+
+/// One entry of the queue, set while its owner holds or waits for the lock
+// on its own cache line, so waiters don't disturb each other
+#[repr(align(64))]
+struct Node {
+    locked: AtomicBool,
+}
+
+/// Fair queue lock by Craig, Landin and Hagersten
+///
+/// A locker appends a node to the queue and spins on the node of the
+/// thread in front of it, which only that thread writes when unlocking.
+/// So the lock is handed over in order and only one waiter sees the
+/// cache line change on each unlock, unlike the ticket lock
+pub struct RawClhLock {
+    /// last node of the queue, null if the lock is free
+    tail: AtomicPtr<Node>,
+    /// node of the holder, only touched with the lock held
+    head: UnsafeCell<*mut Node>,
+}
+
+// head is only touched by the holder
+unsafe impl Send for RawClhLock {}
+unsafe impl Sync for RawClhLock {}
+
+unsafe impl RawLock for RawClhLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawClhLock {
+        tail: AtomicPtr::new(ptr::null_mut()),
+        head: UnsafeCell::new(ptr::null_mut()),
+    };
+
+    fn lock(&self) {
+        let node = Box::into_raw(Box::new(Node {
+            locked: AtomicBool::new(true),
+        }));
+
+        // acquire pairs with the release of the previous tail, so its
+        // node is initialized, or with the unlock that set it to null
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        if !prev.is_null() {
+            unsafe {
+                // synchronizes-with the release in `unlock`
+                spin_while(|| (*prev).locked.load(Ordering::Acquire));
+                // we were the last one to look at it
+                drop(Box::from_raw(prev));
+            }
+        }
+        unsafe { *self.head.get() = node };
+    }
+
+    fn try_lock(&self) -> bool {
+        if !self.tail.load(Ordering::Relaxed).is_null() {
+            return false;
+        }
+
+        let node = Box::into_raw(Box::new(Node {
+            locked: AtomicBool::new(true),
+        }));
+        if self
+            .tail
+            .compare_exchange(ptr::null_mut(), node, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { *self.head.get() = node };
+            true
+        } else {
+            drop(unsafe { Box::from_raw(node) });
+            false
+        }
+    }
+
+    unsafe fn unlock(&self) {
+        let node = *self.head.get();
+
+        // nobody queued behind us, the lock becomes free
+        if self
+            .tail
+            .compare_exchange(node, ptr::null_mut(), Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            drop(Box::from_raw(node));
+            return;
+        }
+
+        // hand over to the next in line, which frees the node
+        (*node).locked.store(false, Ordering::Release);
+    }
+}
+
+impl Drop for RawClhLock {
+    fn drop(&mut self) {
+        // the node of a holder whose guard was leaked
+        let tail = *self.tail.get_mut();
+        if !tail.is_null() {
+            drop(unsafe { Box::from_raw(tail) });
+        }
+    }
+}
+
+#[test]
+fn clh_lock_test_counter() {
+    use std::thread;
+
+    let lock = ClhLock::with_raw(0);
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *lock.lock().unwrap() += 1;
+                }
+            });
+        }
+    });
+    assert_eq!(lock.into_inner().unwrap(), 4000);
+
+    let lock = ClhLock::from(());
+    let guard = lock.lock().unwrap();
+    assert!(lock.try_lock().is_err());
+    drop(guard);
+    assert!(lock.try_lock().is_ok());
+
+    // a leaked guard leaves its node to the drop of the lock
+    std::mem::forget(lock.lock().unwrap());
+}
+
+#[test]
+fn clh_lock_test_fifo() {
+    use super::nonpoison;
+    use std::thread;
+
+    let raw = RawClhLock::INIT;
+    let order = nonpoison::Mutex::new(std::vec::Vec::new());
+
+    raw.lock();
+    thread::scope(|s| {
+        // queue up the threads one after the other
+        for id in 0..4 {
+            let (raw, order) = (&raw, &order);
+            let tail = raw.tail.load(Ordering::Relaxed);
+            s.spawn(move || {
+                raw.lock();
+                order.lock().push(id);
+                unsafe { raw.unlock() };
+            });
+            while raw.tail.load(Ordering::Relaxed) == tail {
+                thread::yield_now();
+            }
+        }
+        unsafe { raw.unlock() };
+    });
+    assert_eq!(order.into_inner(), [0, 1, 2, 3]);
+}
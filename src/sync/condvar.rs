@@ -0,0 +1,247 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use super::{
+    futex,
+    mutex::{self, MutexGuard, RawLock},
+    poison::LockResult,
+};
+
+/// Whether `Condvar::wait_timeout` returned because time ran out
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// Condition variable, blocks a thread until another one changes the
+/// data behind a `Mutex`
+///
+/// Waiting unlocks the mutex and sleeps until notified, then locks it
+/// again before returning. Wakeups may be spurious, so the condition is
+/// checked in a loop, or `wait_while` does that
+pub struct Condvar {
+    /// bumped by every notify, a waiter sleeps as long as it is unchanged
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Condvar {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlocks the mutex of guard and blocks until notified
+    ///
+    /// Fails with the guard inside if the mutex is poisoned on relocking
+    pub fn wait<'a, T: ?Sized, R: RawLock>(
+        &self,
+        guard: MutexGuard<'a, T, R>,
+    ) -> LockResult<MutexGuard<'a, T, R>> {
+        // read before unlocking, a notify after the unlock changes it
+        // and the futex does not sleep then
+        let seq = self.seq.load(Ordering::Relaxed);
+        let lock = mutex::guard_lock(&guard);
+        // the guard stays alive, so we hold the lock
+        unsafe { lock.unlock() };
+        futex::wait(&self.seq, seq);
+        lock.lock();
+        mutex::guard_poison(&guard).guard(guard)
+    }
+
+    /// Waits until condition returns false, checking it right away
+    pub fn wait_while<'a, T: ?Sized, R: RawLock, F>(
+        &self,
+        mut guard: MutexGuard<'a, T, R>,
+        mut condition: F,
+    ) -> LockResult<MutexGuard<'a, T, R>>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// `wait` that gives up after timeout
+    ///
+    /// Returns after at most about timeout even if never notified, the
+    /// condition still has to be checked either way
+    pub fn wait_timeout<'a, T: ?Sized, R: RawLock>(
+        &self,
+        guard: MutexGuard<'a, T, R>,
+        timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T, R>, WaitTimeoutResult)> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let lock = mutex::guard_lock(&guard);
+        // the guard stays alive, so we hold the lock
+        unsafe { lock.unlock() };
+        let woken = futex::wait_timeout(&self.seq, seq, timeout);
+        lock.lock();
+        mutex::guard_poison(&guard).guard((guard, WaitTimeoutResult(!woken)))
+    }
+
+    /// `wait_while` that gives up after timeout, returning the guard with
+    /// condition still true
+    pub fn wait_timeout_while<'a, T: ?Sized, R: RawLock, F>(
+        &self,
+        mut guard: MutexGuard<'a, T, R>,
+        timeout: Duration,
+        mut condition: F,
+    ) -> LockResult<(MutexGuard<'a, T, R>, WaitTimeoutResult)>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let start = Instant::now();
+        while condition(&mut *guard) {
+            let left = match timeout.checked_sub(start.elapsed()) {
+                Some(left) => left,
+                None => return Ok((guard, WaitTimeoutResult(true))),
+            };
+            guard = self.wait_timeout(guard, left)?.0;
+        }
+        Ok((guard, WaitTimeoutResult(false)))
+    }
+
+    /// Wakes one waiting thread
+    pub fn notify_one(&self) {
+        // the mutex orders the data, the counter only has to change
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex::wake_one(&self.seq);
+    }
+
+    /// Wakes every waiting thread
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex::wake_all(&self.seq);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Condvar").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn condvar_test_notify() {
+    use super::mutex::Mutex;
+    use std::thread;
+
+    let ready = Mutex::new(false);
+    let cond = Condvar::new();
+
+    thread::scope(|s| {
+        let waiters: std::vec::Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    let ready = cond.wait_while(ready.lock().unwrap(), |ready| !*ready);
+                    assert!(*ready.unwrap());
+                })
+            })
+            .collect();
+
+        thread::yield_now();
+        *ready.lock().unwrap() = true;
+        cond.notify_all();
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+    });
+
+    // ping pong a counter between two threads with notify_one
+    let turn = Mutex::new(0);
+    thread::scope(|s| {
+        for me in 0..2 {
+            let (turn, cond) = (&turn, &cond);
+            s.spawn(move || {
+                for i in 0..200 {
+                    let mut turn = cond
+                        .wait_while(turn.lock().unwrap(), |turn| *turn % 2 != me)
+                        .unwrap();
+                    assert_eq!(*turn, 2 * i + me);
+                    *turn += 1;
+                    cond.notify_one();
+                }
+            });
+        }
+    });
+    assert_eq!(*turn.lock().unwrap(), 400);
+}
+
+#[test]
+fn condvar_test_timeout() {
+    use super::mutex::Mutex;
+    use std::thread;
+
+    let flag = Mutex::new(false);
+    let cond = Condvar::new();
+
+    let (guard, result) = cond
+        .wait_timeout(flag.lock().unwrap(), Duration::from_millis(10))
+        .unwrap();
+    assert!(result.timed_out());
+    drop(guard);
+
+    let start = Instant::now();
+    let (guard, result) = cond
+        .wait_timeout_while(flag.lock().unwrap(), Duration::from_millis(20), |flag| {
+            !*flag
+        })
+        .unwrap();
+    assert!(result.timed_out() && !*guard);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    drop(guard);
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(5));
+            *flag.lock().unwrap() = true;
+            cond.notify_one();
+        });
+        let (guard, result) = cond
+            .wait_timeout_while(flag.lock().unwrap(), Duration::from_secs(10), |flag| !*flag)
+            .unwrap();
+        assert!(!result.timed_out() && *guard);
+    });
+}
+
+#[test]
+fn condvar_test_poison() {
+    use super::{arc::Arc, mutex::Mutex};
+    use std::thread;
+
+    let pair = Arc::new((Mutex::new(0), Condvar::new()));
+    let (lock, cond) = &*pair;
+
+    // the lock is held until we wait, so the other thread runs meanwhile
+    let guard = lock.lock().unwrap();
+    let panicker = thread::spawn({
+        let pair = pair.clone();
+        move || {
+            let (lock, cond) = &*pair;
+            let mut n = lock.lock().unwrap();
+            *n = 1;
+            cond.notify_all();
+            panic!("poisons while the waiter sleeps");
+        }
+    });
+
+    let err = cond.wait_while(guard, |n| *n == 0).unwrap_err();
+    assert_eq!(*err.into_inner(), 1);
+    assert!(panicker.join().is_err());
+}
@@ -0,0 +1,314 @@
+//! Epoch based memory reclamation for lock-free structures
+//!
+//! A thread reading shared nodes first `pin`s itself, which records the
+//! current global epoch. A node unlinked from a structure is not freed
+//! right away but handed to `Guard::defer_destroy`, stamped with the
+//! epoch. The global epoch only moves on once every pinned thread has
+//! seen the current one, so two epochs after the stamp nobody can still
+//! be reading the node and it is freed.
+//!
+//! This also rules out ABA problems on the structures: the address of a
+//! node is not reused while a pinned thread may still compare against it
+//!
+//! Every thread gets a record in a global list the first time it pins.
+//! Records are reused by later threads, but never freed
+
+use std::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    mem, ptr,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use super::nonpoison::Mutex;
+
+/// Set in `Local::state` while the thread is pinned, the epoch is above it
+const PINNED: usize = 1;
+
+/// Defers between attempts to move the epoch on and free garbage
+const COLLECT_EVERY: usize = 64;
+
+/// A destructor to run once nobody can see its pointer anymore
+struct Deferred {
+    ptr: *mut (),
+    call: unsafe fn(*mut ()),
+    /// global epoch when the pointer was unlinked
+    epoch: usize,
+}
+
+// callers of defer_destroy promise the pointee can be dropped anywhere
+unsafe impl Send for Deferred {}
+
+impl Deferred {
+    fn is_expired(&self, epoch: usize) -> bool {
+        epoch.wrapping_sub(self.epoch) >= 2
+    }
+}
+
+unsafe fn drop_box<T>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+/// Record of one thread
+struct Local {
+    /// epoch << 1 | PINNED while pinned, 0 otherwise
+    state: AtomicUsize,
+    /// owned by a live thread
+    in_use: AtomicBool,
+    /// next record of the global list, fixed once published
+    next: *const Local,
+    /// only touched by the owning thread
+    pins: Cell<usize>,
+    defers: Cell<usize>,
+    garbage: UnsafeCell<std::vec::Vec<Deferred>>,
+}
+
+// the cells are only touched by the owning thread
+unsafe impl Sync for Local {}
+
+struct Global {
+    epoch: AtomicUsize,
+    /// head of the list of all records, only ever pushed to
+    locals: AtomicPtr<Local>,
+    /// garbage left behind by exited threads
+    orphans: Mutex<std::vec::Vec<Deferred>>,
+}
+
+static GLOBAL: Global = Global {
+    epoch: AtomicUsize::new(0),
+    locals: AtomicPtr::new(ptr::null_mut()),
+    orphans: Mutex::new(std::vec::Vec::new()),
+};
+
+/// Owns the record of the current thread, gives it up when it exits
+struct Handle {
+    local: &'static Local,
+}
+
+thread_local! {
+    static HANDLE: Handle = Handle::register();
+}
+
+impl Handle {
+    fn register() -> Handle {
+        // reuse the record of an exited thread
+        let mut cur = GLOBAL.locals.load(Ordering::Acquire);
+        while let Some(local) = unsafe { cur.as_ref() } {
+            if local
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Handle { local };
+            }
+            cur = local.next as *mut Local;
+        }
+
+        let local = Box::leak(Box::new(Local {
+            state: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: ptr::null(),
+            pins: Cell::new(0),
+            defers: Cell::new(0),
+            garbage: UnsafeCell::new(std::vec::Vec::new()),
+        }));
+        let mut head = GLOBAL.locals.load(Ordering::Relaxed);
+        loop {
+            local.next = head;
+            match GLOBAL.locals.compare_exchange_weak(
+                head,
+                local,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Handle { local },
+                Err(cur) => head = cur,
+            }
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let garbage = mem::take(unsafe { &mut *self.local.garbage.get() });
+        GLOBAL.orphans.lock().extend(garbage);
+        // synchronizes-with the acquire in `register`
+        self.local.in_use.store(false, Ordering::Release);
+    }
+}
+
+/// Keeps the current thread pinned, pointers read from lock-free
+/// structures stay valid while it lives
+///
+/// Guards nest, the thread is unpinned when the last one drops
+pub struct Guard {
+    local: &'static Local,
+    // unpins the thread it was pinned on
+    phantom: PhantomData<*const ()>,
+}
+
+/// Pins the current thread
+///
+/// # Panics
+///
+/// When called from the destructor of a thread local after the record
+/// of the thread is gone
+pub fn pin() -> Guard {
+    let local = HANDLE.with(|handle| handle.local);
+    let pins = local.pins.get();
+    local.pins.set(pins + 1);
+    if pins == 0 {
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        local.state.store(epoch << 1 | PINNED, Ordering::Relaxed);
+        // the store has to be visible before we read any shared pointer,
+        // pairs with the fence in `try_advance`
+        fence(Ordering::SeqCst);
+    }
+    Guard {
+        local,
+        phantom: PhantomData,
+    }
+}
+
+/// Moves the global epoch on if every pinned thread is in the current
+/// one, returns the epoch after that
+fn try_advance() -> usize {
+    let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+
+    let mut cur = GLOBAL.locals.load(Ordering::Acquire);
+    while let Some(local) = unsafe { cur.as_ref() } {
+        let state = local.state.load(Ordering::Relaxed);
+        if state & PINNED != 0 && state >> 1 != epoch {
+            return epoch;
+        }
+        cur = local.next as *mut Local;
+    }
+    // whatever the threads read before unpinning happens-before the
+    // frees that this advance allows
+    fence(Ordering::Acquire);
+
+    match GLOBAL.epoch.compare_exchange(
+        epoch,
+        epoch.wrapping_add(1),
+        Ordering::Release,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => epoch.wrapping_add(1),
+        Err(cur) => cur,
+    }
+}
+
+/// Runs the expired destructors of garbage, keeps the rest
+fn collect(garbage: &mut std::vec::Vec<Deferred>, epoch: usize) -> std::vec::Vec<Deferred> {
+    let (expired, live) = mem::take(garbage)
+        .into_iter()
+        .partition(|deferred| deferred.is_expired(epoch));
+    *garbage = live;
+    expired
+}
+
+fn run(expired: std::vec::Vec<Deferred>) {
+    for deferred in expired {
+        unsafe { (deferred.call)(deferred.ptr) };
+    }
+}
+
+impl Guard {
+    /// Frees the box behind ptr once no pinned thread can see it anymore
+    ///
+    /// # Safety
+    ///
+    /// ptr comes from `Box::into_raw`, is already unreachable for threads
+    /// pinning from now on, and is destroyed only here. T has to be fine
+    /// to drop on another thread
+    pub unsafe fn defer_destroy<T>(&self, ptr: *mut T) {
+        // stamp after the unlink, pairs with the fence in `pin`
+        fence(Ordering::SeqCst);
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        (*self.local.garbage.get()).push(Deferred {
+            ptr: ptr as *mut (),
+            call: drop_box::<T>,
+            epoch,
+        });
+
+        let defers = self.local.defers.get() + 1;
+        self.local.defers.set(defers);
+        if defers.is_multiple_of(COLLECT_EVERY) {
+            self.flush();
+        }
+    }
+
+    /// Tries to move the epoch on and runs the destructors whose time
+    /// has come
+    pub fn flush(&self) {
+        let epoch = try_advance();
+        // the destructors may pin and defer themselves, run them after
+        // letting go of the garbage lists
+        let expired = collect(unsafe { &mut *self.local.garbage.get() }, epoch);
+        run(expired);
+
+        let orphans = collect(&mut GLOBAL.orphans.lock(), epoch);
+        run(orphans);
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let pins = self.local.pins.get() - 1;
+        self.local.pins.set(pins);
+        if pins == 0 {
+            // the reads of the guard happen-before the epoch moves on
+            self.local.state.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[test]
+fn epoch_test_pinned_blocks_free() {
+    use super::{arc::DropCounter, barrier::Barrier};
+    use std::thread;
+
+    let barrier = Barrier::new(2);
+    let drops = DropCounter::count();
+    let flush_until = |count: usize| {
+        // other tests may hold pins for a moment
+        for _ in 0..10_000 {
+            pin().flush();
+            if drops.load(Ordering::Relaxed) == count {
+                return true;
+            }
+            thread::yield_now();
+        }
+        false
+    };
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            let guard = pin();
+            barrier.wait();
+            barrier.wait();
+            drop(guard);
+        });
+
+        barrier.wait();
+        {
+            let guard = pin();
+            let nested = pin();
+            let counter = Box::new(DropCounter(drops.clone()));
+            unsafe { nested.defer_destroy(Box::into_raw(counter)) };
+            drop(guard);
+        }
+        // the other thread may still see it
+        assert!(!flush_until(1));
+        barrier.wait();
+    });
+    assert!(flush_until(1));
+
+    // garbage of exited threads is freed by the others
+    let counter = Box::new(DropCounter(drops.clone()));
+    thread::spawn(move || unsafe { pin().defer_destroy(Box::into_raw(counter)) })
+        .join()
+        .unwrap();
+    assert!(flush_until(2));
+}
@@ -0,0 +1,356 @@
+//! Sleeping on an atomic, the building block of the blocking locks
+//!
+//! `wait` blocks while the atomic holds the expected value until `wake_one`
+//! or `wake_all` is called on it, `wait_timeout` gives up after a while.
+//! Wakeups may be spurious, callers check
+//! their condition again. Uses the futex syscall on Linux, and a small
+//! parking lot of `std::thread::park` waiters keyed by address elsewhere
+
+use std::{
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+#[cfg(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "riscv64"
+    )
+))]
+mod imp {
+    use std::{os::raw::c_long, ptr, sync::atomic::AtomicU32, time::Duration};
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_FUTEX: c_long = 202;
+    #[cfg(any(target_arch = "x86", target_arch = "arm"))]
+    const SYS_FUTEX: c_long = 240;
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    const SYS_FUTEX: c_long = 98;
+
+    const FUTEX_WAIT: c_long = 0;
+    const FUTEX_WAKE: c_long = 1;
+    // the futex is never shared with another process
+    const FUTEX_PRIVATE_FLAG: c_long = 128;
+
+    extern "C" {
+        // from the libc std links against
+        fn syscall(num: c_long, ...) -> c_long;
+    }
+
+    /// struct timespec, time_t is a long on the targets above
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+
+    pub fn wait(atomic: &AtomicU32, expected: u32) {
+        // returns right away if the value changed,
+        // EINTR and EAGAIN are fine, the caller loops
+        unsafe {
+            syscall(
+                SYS_FUTEX,
+                atomic.as_ptr(),
+                FUTEX_WAIT | FUTEX_PRIVATE_FLAG,
+                expected,
+                ptr::null::<Timespec>(),
+            );
+        }
+    }
+
+    pub fn wait_timeout(atomic: &AtomicU32, expected: u32, timeout: Duration) {
+        // FUTEX_WAIT takes a relative timeout
+        let timeout = Timespec {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(c_long::MAX),
+            tv_nsec: timeout.subsec_nanos() as c_long,
+        };
+        unsafe {
+            syscall(
+                SYS_FUTEX,
+                atomic.as_ptr(),
+                FUTEX_WAIT | FUTEX_PRIVATE_FLAG,
+                expected,
+                &timeout as *const Timespec,
+            );
+        }
+    }
+
+    fn wake(atomic: &AtomicU32, count: i32) {
+        unsafe {
+            syscall(
+                SYS_FUTEX,
+                atomic.as_ptr(),
+                FUTEX_WAKE | FUTEX_PRIVATE_FLAG,
+                count,
+            );
+        }
+    }
+
+    pub fn wake_one(atomic: &AtomicU32) {
+        wake(atomic, 1);
+    }
+
+    pub fn wake_all(atomic: &AtomicU32) {
+        wake(atomic, i32::MAX);
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "riscv64"
+    )
+)))]
+use park as imp;
+
+/// Fallback for platforms without a futex
+///
+/// Waiters are queued in one of a few buckets picked by the address of the
+/// atomic, and parked until a waker takes them out of the queue
+// only used by the tests where the futex is available
+#[allow(dead_code)]
+mod park {
+    use std::{
+        cell::UnsafeCell,
+        hint, ptr,
+        sync::atomic::{AtomicBool, AtomicU32, Ordering},
+        thread::{self, Thread},
+        time::{Duration, Instant},
+    };
+
+    struct Waiter {
+        addr: usize,
+        thread: Thread,
+        /// lives on the stack of the waiting thread,
+        /// which only returns once it is set
+        woken: *const AtomicBool,
+    }
+
+    struct Bucket {
+        locked: AtomicBool,
+        queue: UnsafeCell<std::vec::Vec<Waiter>>,
+    }
+
+    // the queue is only touched with the bucket locked
+    unsafe impl Sync for Bucket {}
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Bucket = Bucket {
+        locked: AtomicBool::new(false),
+        queue: UnsafeCell::new(std::vec::Vec::new()),
+    };
+
+    static BUCKETS: [Bucket; 64] = [EMPTY; 64];
+
+    /// Runs f with the queue of the bucket of atomic locked
+    fn with_queue<R>(atomic: &AtomicU32, f: impl FnOnce(&mut std::vec::Vec<Waiter>) -> R) -> R {
+        let addr = atomic as *const AtomicU32 as usize;
+        let bucket = &BUCKETS[(addr >> 2) % BUCKETS.len()];
+
+        // held for a few instructions only, spinning is fine
+        while bucket
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *bucket.queue.get() });
+        bucket.locked.store(false, Ordering::Release);
+        result
+    }
+
+    pub fn wait(atomic: &AtomicU32, expected: u32) {
+        wait_until(atomic, expected, None);
+    }
+
+    pub fn wait_timeout(atomic: &AtomicU32, expected: u32, timeout: Duration) {
+        wait_until(atomic, expected, Instant::now().checked_add(timeout));
+    }
+
+    fn wait_until(atomic: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+        let woken = AtomicBool::new(false);
+
+        // checking the value under the bucket lock closes the gap with a
+        // waker that changes it and then takes the lock
+        let queued = with_queue(atomic, |queue| {
+            if atomic.load(Ordering::SeqCst) != expected {
+                return false;
+            }
+            queue.push(Waiter {
+                addr: atomic as *const AtomicU32 as usize,
+                thread: thread::current(),
+                woken: &woken,
+            });
+            true
+        });
+
+        if !queued {
+            return;
+        }
+
+        while !woken.load(Ordering::Acquire) {
+            let Some(deadline) = deadline else {
+                thread::park();
+                continue;
+            };
+            let now = Instant::now();
+            if now < deadline {
+                thread::park_timeout(deadline - now);
+                continue;
+            }
+
+            // timed out, leave the queue. if a waker got to us first
+            // it still sets woken, which has to outlive that
+            let left = with_queue(atomic, |queue| {
+                let idx = queue
+                    .iter()
+                    .position(|waiter| ptr::eq(waiter.woken, &woken));
+                idx.map(|idx| queue.remove(idx)).is_some()
+            });
+            if left {
+                return;
+            }
+            while !woken.load(Ordering::Acquire) {
+                thread::park();
+            }
+        }
+    }
+
+    fn wake(atomic: &AtomicU32, mut count: usize) {
+        let addr = atomic as *const AtomicU32 as usize;
+        let mut woken = std::vec::Vec::new();
+
+        with_queue(atomic, |queue| {
+            let mut idx = 0;
+            while idx < queue.len() && count > 0 {
+                if queue[idx].addr == addr {
+                    woken.push(queue.remove(idx));
+                    count -= 1;
+                } else {
+                    idx += 1;
+                }
+            }
+        });
+
+        for waiter in woken {
+            // the waiter may return as soon as this is set,
+            // only the owned thread handle is used after
+            unsafe { (*waiter.woken).store(true, Ordering::Release) };
+            waiter.thread.unpark();
+        }
+    }
+
+    pub fn wake_one(atomic: &AtomicU32) {
+        wake(atomic, 1);
+    }
+
+    pub fn wake_all(atomic: &AtomicU32) {
+        wake(atomic, usize::MAX);
+    }
+}
+
+/// Blocks while atomic holds expected, may wake up spuriously
+pub fn wait(atomic: &AtomicU32, expected: u32) {
+    imp::wait(atomic, expected)
+}
+
+/// `wait` for at most timeout, returns false if it ran out
+///
+/// Like `wait`, a true return may still be spurious
+pub fn wait_timeout(atomic: &AtomicU32, expected: u32, timeout: Duration) -> bool {
+    let start = Instant::now();
+    imp::wait_timeout(atomic, expected, timeout);
+    start.elapsed() < timeout
+}
+
+/// Wakes one thread waiting on atomic
+pub fn wake_one(atomic: &AtomicU32) {
+    imp::wake_one(atomic)
+}
+
+/// Wakes every thread waiting on atomic
+pub fn wake_all(atomic: &AtomicU32) {
+    imp::wake_all(atomic)
+}
+
+#[cfg(test)]
+fn futex_test_ping_pong(wait: fn(&AtomicU32, u32), wake_one: fn(&AtomicU32)) {
+    use std::{sync::atomic::Ordering, thread};
+
+    // two threads take turns flipping the value, each sleeping on the other
+    let turn = AtomicU32::new(0);
+    thread::scope(|s| {
+        for me in 0..2 {
+            let turn = &turn;
+            s.spawn(move || {
+                for _ in 0..500 {
+                    loop {
+                        let cur = turn.load(Ordering::Acquire);
+                        if cur == me {
+                            break;
+                        }
+                        wait(turn, cur);
+                    }
+                    turn.store(1 - me, Ordering::Release);
+                    wake_one(turn);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn futex_test_wait_wake() {
+    // a changed value returns right away
+    let atomic = AtomicU32::new(1);
+    wait(&atomic, 0);
+    park::wait(&atomic, 0);
+    wake_one(&atomic);
+
+    futex_test_ping_pong(wait, wake_one);
+    futex_test_ping_pong(park::wait, park::wake_one);
+}
+
+#[cfg(test)]
+fn futex_test_timeout(wait_timeout: fn(&AtomicU32, u32, Duration), wake_all: fn(&AtomicU32)) {
+    use std::{sync::atomic::Ordering, thread};
+
+    // nobody wakes us
+    let atomic = AtomicU32::new(0);
+    let start = Instant::now();
+    wait_timeout(&atomic, 0, Duration::from_millis(20));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // a wake comes long before the timeout
+    let start = Instant::now();
+    thread::scope(|s| {
+        s.spawn(|| {
+            while atomic.load(Ordering::Acquire) == 0 {
+                wait_timeout(&atomic, 0, Duration::from_secs(10));
+            }
+        });
+        thread::sleep(Duration::from_millis(5));
+        atomic.store(1, Ordering::Release);
+        wake_all(&atomic);
+    });
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn futex_test_wait_timeout() {
+    let atomic = AtomicU32::new(0);
+    assert!(!wait_timeout(&atomic, 0, Duration::from_millis(1)));
+    assert!(wait_timeout(&atomic, 1, Duration::from_secs(10)));
+
+    futex_test_timeout(imp::wait_timeout, wake_all);
+    futex_test_timeout(park::wait_timeout, park::wake_all);
+}
@@ -0,0 +1,124 @@
+use std::{
+    cell::Cell,
+    fmt,
+    ops::Deref,
+    panic::{RefUnwindSafe, UnwindSafe},
+};
+
+use super::once_lock::OnceLock;
+
+/// A value computed by f on first access, usable from many threads
+///
+/// Meant for globals, `static TABLE: LazyLock<Table> = LazyLock::new(build)`.
+/// If f panics, the lock is poisoned and every later access panics
+pub struct LazyLock<T, F = fn() -> T> {
+    cell: OnceLock<T>,
+    /// taken by the one thread initializing the cell
+    init: Cell<Option<F>>,
+}
+
+// init is only touched inside the initializer of cell, by one thread
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyLock<T, F> {}
+
+impl<T: UnwindSafe, F: UnwindSafe> RefUnwindSafe for LazyLock<T, F> {}
+
+impl<T, F: FnOnce() -> T> LazyLock<T, F> {
+    pub const fn new(f: F) -> Self {
+        LazyLock {
+            cell: OnceLock::new(),
+            init: Cell::new(Some(f)),
+        }
+    }
+
+    /// The value, computing it first if nobody did yet
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(f) => f(),
+            None => panic!("LazyLock instance has previously been poisoned"),
+        })
+    }
+
+    /// The value if it was computed, f otherwise
+    ///
+    /// # Panics
+    ///
+    /// If the lock is poisoned
+    pub fn into_inner(this: Self) -> Result<T, F> {
+        let LazyLock { cell, init } = this;
+        cell.into_inner().ok_or_else(|| match init.into_inner() {
+            Some(f) => f,
+            None => panic!("LazyLock instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyLock<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        LazyLock::force(self)
+    }
+}
+
+impl<T: Default> Default for LazyLock<T> {
+    fn default() -> Self {
+        LazyLock::new(T::default)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for LazyLock<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("LazyLock");
+        match self.cell.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+#[test]
+fn lazy_lock_test_race() {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    static SQUARES: LazyLock<HashMap<u32, u32>> = LazyLock::new(|| {
+        BUILDS.fetch_add(1, Ordering::Relaxed);
+        thread::yield_now();
+        (0..100).map(|i| (i, i * i)).collect()
+    });
+
+    thread::scope(|s| {
+        for i in 0..16 {
+            s.spawn(move || assert_eq!(SQUARES[&i], i * i));
+        }
+    });
+    assert_eq!(BUILDS.load(Ordering::Relaxed), 1);
+    assert_eq!(SQUARES.len(), 100);
+}
+
+#[test]
+fn lazy_lock_test_api() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let lazy = LazyLock::new(|| 6 * 7);
+    assert_eq!(format!("{:?}", lazy), "LazyLock(<uninit>)");
+    assert_eq!(*lazy, 42);
+    assert_eq!(format!("{:?}", lazy), "LazyLock(42)");
+    assert_eq!(LazyLock::into_inner(lazy).ok(), Some(42));
+
+    let lazy = LazyLock::new(|| 1);
+    let f = LazyLock::into_inner(lazy).unwrap_err();
+    assert_eq!(f(), 1);
+
+    let lazy: LazyLock<i32> = LazyLock::new(|| panic!("init"));
+    assert!(catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+    // poisoned for good
+    assert!(catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+
+    assert_eq!(*LazyLock::<std::vec::Vec<u8>>::default(), []);
+}
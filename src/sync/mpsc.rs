@@ -0,0 +1,532 @@
+//! Multi producer single consumer channels
+//!
+//! `channel` creates an unbounded channel, where `send` never blocks, and
+//! `bounded` one holding at most a fixed number of messages, where `send`
+//! blocks while it is full. `Sender` can be cloned to send from many
+//! threads, the one `Receiver` gets the messages in the order they were
+//! sent. Once all senders are gone, the receiver drains the remaining
+//! messages and then reports the disconnect, and the other way around
+
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use super::{
+    arc::Arc,
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+};
+use crate::collection::deque::Deque;
+
+struct State<T> {
+    queue: Deque<T>,
+    senders: usize,
+    receiver: bool,
+}
+
+/// Shared between all senders and the receiver
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// None for unbounded channels
+    capacity: Option<usize>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+// the queue is only touched with the mutex held
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Arc<Self> {
+        Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: Deque::new(),
+                senders: 1,
+                receiver: true,
+            }),
+            capacity,
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        })
+    }
+
+    /// Locks the state, no code panics while holding it, so it can't be
+    /// poisoned by anything but a panicking allocation
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        self.capacity
+            .is_some_and(|capacity| state.queue.len() >= capacity)
+    }
+}
+
+/// Sending half of a channel, clone it for more producers
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving half of a channel
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // only one thread receives at a time
+    phantom: PhantomData<Cell<()>>,
+}
+
+/// Creates a channel without a limit on the queued messages
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Shared::new(None);
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            phantom: PhantomData,
+        },
+    )
+}
+
+/// Creates a channel queueing at most capacity messages
+///
+/// # Panics
+///
+/// If capacity is 0
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "bounded channel needs a capacity");
+    let shared = Shared::new(Some(capacity));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            phantom: PhantomData,
+        },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Queues value, blocking while a bounded channel is full
+    ///
+    /// Fails with the value if the receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        while state.receiver && shared.is_full(&state) {
+            state = shared
+                .not_full
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        self.push(state, value).map_err(SendError)
+    }
+
+    /// Queues value if there is room, without waiting
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let state = shared.lock();
+        if state.receiver && shared.is_full(&state) {
+            return Err(TrySendError::Full(value));
+        }
+        self.push(state, value).map_err(TrySendError::Disconnected)
+    }
+
+    fn push(&self, mut state: MutexGuard<'_, State<T>>, value: T) -> Result<(), T> {
+        if !state.receiver {
+            return Err(value);
+        }
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            // a waiting receiver learns about the disconnect
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message arrives
+    ///
+    /// Fails once all senders are gone and the queue is drained
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if let Some(value) = self.pop(&mut state) {
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Takes a message if there is one, without waiting
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.pop(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// `recv` that gives up after timeout
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let shared = &*self.shared;
+        let start = Instant::now();
+        let mut state = shared.lock();
+        loop {
+            if let Some(value) = self.pop(&mut state) {
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let left = timeout
+                .checked_sub(start.elapsed())
+                .ok_or(RecvTimeoutError::Timeout)?;
+            state = shared
+                .not_empty
+                .wait_timeout(state, left)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+    }
+
+    fn pop(&self, state: &mut MutexGuard<'_, State<T>>) -> Option<T> {
+        let value = state.queue.pop_front()?;
+        if self.shared.capacity.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        Some(value)
+    }
+
+    /// Blocking iterator over the messages, ends when all senders are gone
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// Iterator over the messages queued right now, never blocks
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver = false;
+        // drop the messages now, not when the last sender goes away
+        let queue = std::mem::take(&mut state.queue);
+        drop(state);
+        self.shared.not_full.notify_all();
+        drop(queue);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+pub struct Iter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+pub struct TryIter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+/// The receiver is gone, the message is handed back
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+/// Why a `try_send` failed, the message is handed back
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// a bounded channel is full
+    Full(T),
+    Disconnected(T),
+}
+
+/// All senders are gone and the queue is drained
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecvError;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+impl<T> From<SendError<T>> for TrySendError<T> {
+    fn from(err: SendError<T>) -> Self {
+        TrySendError::Disconnected(err.0)
+    }
+}
+
+// the messages may not be Debug, like std
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on channel"),
+            RecvTimeoutError::Disconnected => {
+                f.write_str("channel is empty and sending half is closed")
+            }
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[test]
+fn mpsc_test_unbounded() {
+    use std::thread;
+
+    let (tx, rx) = channel();
+
+    thread::scope(|s| {
+        for id in 0..4 {
+            let tx = tx.clone();
+            s.spawn(move || {
+                for i in 0..500 {
+                    tx.send((id, i)).unwrap();
+                }
+            });
+        }
+    });
+    drop(tx);
+
+    // per sender the order is kept
+    let mut next = [0; 4];
+    for (id, i) in &rx {
+        assert_eq!(next[id], i);
+        next[id] += 1;
+    }
+    assert_eq!(next, [500; 4]);
+    assert_eq!(rx.recv(), Err(RecvError));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn mpsc_test_bounded() {
+    use std::thread;
+
+    let (tx, rx) = bounded(2);
+    tx.send(1).unwrap();
+    tx.try_send(2).unwrap();
+    assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+
+    thread::scope(|s| {
+        // blocks until the receiver makes room
+        let sender = s.spawn(|| {
+            for i in 3..100 {
+                tx.send(i).unwrap();
+            }
+        });
+        let received: std::vec::Vec<_> = rx.iter().take(99).collect();
+        assert_eq!(received, (1..100).collect::<std::vec::Vec<_>>());
+        sender.join().unwrap();
+    });
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+    // a sender blocked on a full channel wakes up when the receiver drops
+    tx.send(0).unwrap();
+    tx.send(0).unwrap();
+    thread::scope(|s| {
+        let sender = s.spawn(|| tx.send(7));
+        thread::yield_now();
+        drop(rx);
+        assert_eq!(sender.join().unwrap(), Err(SendError(7)));
+    });
+    assert!(matches!(tx.try_send(8), Err(TrySendError::Disconnected(8))));
+}
+
+#[test]
+fn mpsc_test_disconnect() {
+    use super::arc::DropCounter;
+    use std::{sync::atomic::Ordering, thread};
+
+    let (tx, rx) = channel::<i32>();
+    assert_eq!(
+        rx.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    );
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            tx.send(1).unwrap();
+            // dropping the last sender disconnects
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(1));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    });
+
+    // queued messages still arrive after the senders are gone
+    let (tx, rx) = bounded(4);
+    tx.send(1).unwrap();
+    tx.send(2).unwrap();
+    drop(tx);
+    assert_eq!(rx.try_iter().collect::<std::vec::Vec<_>>(), [1, 2]);
+    assert_eq!(rx.into_iter().next(), None);
+
+    // messages left in the channel are dropped with the receiver
+    let drops = DropCounter::count();
+    let (tx, rx) = channel();
+    for _ in 0..3 {
+        tx.send(DropCounter(drops.clone())).unwrap();
+    }
+    drop(rx);
+    assert_eq!(drops.load(Ordering::Relaxed), 3);
+    let err = tx.send(DropCounter(drops.clone())).unwrap_err();
+    assert_eq!(err.to_string(), "sending on a closed channel");
+    drop(err);
+    assert_eq!(drops.load(Ordering::Relaxed), 4);
+}
@@ -1,29 +1,463 @@
-use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-pub struct Mutex {
-    flag: AtomicBool,
+use super::{
+    futex,
+    poison::{self, LockResult, TryLockError, TryLockResult},
+};
+
+/// A lock without data, the strategy behind a `Mutex`
+///
+/// # Safety
+///
+/// `lock` and a successful `try_lock` have to exclude every other holder
+/// until `unlock`, with acquire/release ordering between them
+pub unsafe trait RawLock {
+    /// An unlocked lock
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self;
+
+    /// Blocks until the lock is free
+    fn lock(&self);
+
+    fn try_lock(&self) -> bool;
+
+    /// # Safety
+    ///
+    /// Only the current holder of the lock may unlock it
+    unsafe fn unlock(&self);
 }
 
-impl Mutex {
-    pub fn new() -> Self {
-        Self {
-            flag: AtomicBool::new(false),
+/// Spins until cond turns false, yielding to other threads once it
+/// takes longer than a few spins
+pub(crate) fn spin_while(mut cond: impl FnMut() -> bool) {
+    let mut spins = 0;
+    while cond() {
+        if spins < SPIN_LIMIT {
+            std::hint::spin_loop();
+            spins += 1;
+        } else {
+            // the holder may not be running at all
+            std::thread::yield_now();
         }
     }
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// locked, and other threads may sleep waiting for it
+const CONTENDED: u32 = 2;
+
+/// Spins in `lock` before going to sleep
+const SPIN_LIMIT: usize = 100;
 
-    pub fn lock(&self) {
-        // Wait until the flag to be false
-        while self
-            .flag
-            .compare_exchange_weak(false, true, Ordering::Relaxed, Ordering::Relaxed)
+/// The default lock of a `Mutex`
+///
+/// Spins for a short while when the lock is taken,
+/// then sleeps until the holder wakes it up. Not fair, a thread unlocking
+/// and locking right away usually gets the lock again
+pub struct RawMutex {
+    state: AtomicU32,
+}
+
+impl RawMutex {
+    #[cold]
+    fn lock_contended(&self) {
+        // the holder may be about to unlock, spin before sleeping.
+        // only while nobody sleeps, they would be first in line anyway
+        let mut spins = 0;
+        while self.state.load(Ordering::Relaxed) == LOCKED && spins < SPIN_LIMIT {
+            std::hint::spin_loop();
+            spins += 1;
+        }
+
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+
+        // from here on we may be sleeping, so take the lock as contended.
+        // the unlock of the holder then knows it has to wake somebody
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex::wait(&self.state, CONTENDED);
+        }
+    }
+}
+
+unsafe impl RawLock for RawMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawMutex {
+        state: AtomicU32::new(UNLOCKED),
+    };
+
+    fn lock(&self) {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
-        {}
+        {
+            self.lock_contended();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Releases the lock, waking a sleeper if there is one
+    unsafe fn unlock(&self) {
+        // synchronizes-with the acquire in `lock`
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex::wake_one(&self.state);
+        }
+    }
+}
+
+/// Lock protecting a value of T
+///
+/// If a thread panics while holding the lock, the mutex is poisoned and
+/// `lock` returns the guard wrapped in a `PoisonError` from then on. See
+/// `nonpoison::Mutex` for a mutex that ignores panics
+///
+/// R picks how threads wait for the lock, see `TicketLock` and `ClhLock`
+/// for fair ones
+pub struct Mutex<T: ?Sized, R: RawLock = RawMutex> {
+    raw: R,
+    poison: poison::Flag,
+    data: UnsafeCell<T>,
+}
+
+/// Access to the data of a locked `Mutex`, unlocks it when dropped
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized, R: RawLock = RawMutex> {
+    lock: &'a Mutex<T, R>,
+    poison: poison::Guard,
+    // like std, a guard is unlocked on the thread that locked it
+    phantom: PhantomData<*const ()>,
+}
 
-        // synchronizes-with release-store in fn `unlock`
-        fence(Ordering::Acquire)
+/// A `MutexGuard` narrowed to a part of the data by `MutexGuard::map`
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MappedMutexGuard<'a, T: ?Sized, R: RawLock = RawMutex> {
+    raw: &'a R,
+    poison_flag: &'a poison::Flag,
+    poison: poison::Guard,
+    data: *mut T,
+    phantom: PhantomData<&'a mut T>,
+}
+
+unsafe impl<T: ?Sized + Send, R: RawLock + Send> Send for Mutex<T, R> {}
+unsafe impl<T: ?Sized + Send, R: RawLock + Sync> Sync for Mutex<T, R> {}
+
+unsafe impl<T: ?Sized + Sync, R: RawLock + Sync> Sync for MutexGuard<'_, T, R> {}
+unsafe impl<T: ?Sized + Sync, R: RawLock + Sync> Sync for MappedMutexGuard<'_, T, R> {}
+
+// a panic while holding the lock is reported through poisoning
+impl<T: ?Sized, R: RawLock> UnwindSafe for Mutex<T, R> {}
+impl<T: ?Sized, R: RawLock> RefUnwindSafe for Mutex<T, R> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self::with_raw(data)
+    }
+}
+
+impl<T, R: RawLock> Mutex<T, R> {
+    /// A mutex locked with the strategy R, `TicketLock::with_raw(data)`
+    pub const fn with_raw(data: T) -> Self {
+        Self {
+            raw: R::INIT,
+            poison: poison::Flag::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(poison::PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
     }
+}
 
-    pub fn unlock(&self) {
-        self.flag.store(false, Ordering::Release);
+impl<T: ?Sized, R: RawLock> Mutex<T, R> {
+    /// Blocks until the lock is free
+    ///
+    /// Fails with the guard inside if the mutex is poisoned
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T, R>> {
+        self.raw.lock();
+        unsafe { MutexGuard::new(self) }
     }
+
+    /// Locks the mutex if it is free, without waiting
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T, R>> {
+        if self.raw.try_lock() {
+            Ok(unsafe { MutexGuard::new(self) }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// True if a thread panicked while holding the lock
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Marks the data as consistent again after a panic
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Mutable access without locking, the borrow proves nobody else
+    /// can hold the lock
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        self.poison.guard(data)
+    }
+}
+
+impl<T: Default, R: RawLock> Default for Mutex<T, R> {
+    fn default() -> Self {
+        Self::with_raw(T::default())
+    }
+}
+
+impl<T, R: RawLock> From<T> for Mutex<T, R> {
+    fn from(data: T) -> Self {
+        Self::with_raw(data)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, R: RawLock> fmt::Debug for Mutex<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.poison.get());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<'a, T: ?Sized, R: RawLock> MutexGuard<'a, T, R> {
+    /// # Safety
+    ///
+    /// lock has to be locked by the caller
+    unsafe fn new(lock: &'a Mutex<T, R>) -> LockResult<MutexGuard<'a, T, R>> {
+        lock.poison.guard(MutexGuard {
+            lock,
+            poison: lock.poison.track(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Narrows the guard to a part of the data, the mutex stays locked
+    /// until the returned guard is dropped
+    pub fn map<U: ?Sized, F>(orig: Self, f: F) -> MappedMutexGuard<'a, U, R>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let lock = orig.lock;
+        // a panic in f still unlocks through orig
+        let data = f(unsafe { &mut *lock.data.get() }) as *mut U;
+        let poison = orig.poison;
+        std::mem::forget(orig);
+
+        MappedMutexGuard {
+            raw: &lock.raw,
+            poison_flag: &lock.poison,
+            poison,
+            data,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// The lock behind a guard, for `Condvar` to unlock and relock it while
+/// the guard stays alive
+pub(crate) fn guard_lock<'a, T: ?Sized, R: RawLock>(guard: &MutexGuard<'a, T, R>) -> &'a R {
+    &guard.lock.raw
+}
+
+pub(crate) fn guard_poison<'a, T: ?Sized, R: RawLock>(
+    guard: &MutexGuard<'a, T, R>,
+) -> &'a poison::Flag {
+    &guard.lock.poison
+}
+
+impl<T: ?Sized, R: RawLock> Deref for MutexGuard<'_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, R: RawLock> DerefMut for MutexGuard<'_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, R: RawLock> Drop for MutexGuard<'_, T, R> {
+    fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
+        unsafe { self.lock.raw.unlock() };
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, R: RawLock> fmt::Debug for MutexGuard<'_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, R: RawLock> Deref for MappedMutexGuard<'_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: ?Sized, R: RawLock> DerefMut for MappedMutexGuard<'_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<T: ?Sized, R: RawLock> Drop for MappedMutexGuard<'_, T, R> {
+    fn drop(&mut self) {
+        self.poison_flag.done(&self.poison);
+        unsafe { self.raw.unlock() };
+    }
+}
+
+#[test]
+fn mutex_test_counter() {
+    use super::arc::Arc;
+    use std::thread;
+
+    // more threads than cores, so some of them end up sleeping
+    let counter = Arc::new(Mutex::new(0));
+    let handles: std::vec::Vec<_> = (0..8)
+        .map(|_| {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    *counter.lock().unwrap() += 1;
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*counter.lock().unwrap(), 8000);
+}
+
+#[test]
+fn mutex_test_guard() {
+    let mut mutex = Mutex::new((1, String::from("a")));
+
+    let guard = mutex.lock().unwrap();
+    assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+    assert_eq!(
+        format!("{:?}", mutex),
+        "Mutex { data: <locked>, poisoned: false, .. }"
+    );
+    drop(guard);
+
+    {
+        let mut name = MutexGuard::map(mutex.lock().unwrap(), |data| &mut data.1);
+        name.push('b');
+        assert!(mutex.try_lock().is_err());
+    }
+
+    mutex.try_lock().unwrap().0 += 1;
+    mutex.get_mut().unwrap().0 *= 10;
+    assert_eq!(mutex.into_inner().unwrap(), (20, String::from("ab")));
+
+    let slice: &Mutex<[u8]> = &Mutex::new([1, 2, 3]);
+    slice.lock().unwrap()[0] = 9;
+    assert_eq!(&*slice.lock().unwrap(), &[9, 2, 3]);
+}
+
+#[test]
+fn mutex_test_poison() {
+    use super::arc::Arc;
+    use std::thread;
+
+    let mutex = Arc::new(Mutex::new(vec![1]));
+
+    let result = thread::spawn({
+        let mutex = mutex.clone();
+        move || {
+            let mut data = mutex.lock().unwrap();
+            data.push(2);
+            panic!("poison the mutex");
+        }
+    })
+    .join();
+    assert!(result.is_err());
+
+    // the lock was released, the data is handed out through the error
+    assert!(mutex.is_poisoned());
+    let err = mutex.lock().unwrap_err();
+    assert_eq!(err.to_string(), "poisoned lock: another task failed inside");
+    assert_eq!(*err.into_inner(), [1, 2]);
+    assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+
+    mutex.clear_poison();
+    assert!(!mutex.is_poisoned());
+    mutex.lock().unwrap().push(3);
+
+    // mapped guards poison too
+    let result = thread::spawn({
+        let mutex = mutex.clone();
+        move || {
+            let _first = MutexGuard::map(mutex.lock().unwrap(), |data| &mut data[0]);
+            panic!("poison through the mapped guard");
+        }
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(mutex.is_poisoned());
+
+    // a guard taken while already panicking does not poison
+    let other = Mutex::new(0);
+    let _ = std::panic::catch_unwind(|| {
+        struct LockOnDrop<'a>(&'a Mutex<i32>);
+
+        impl Drop for LockOnDrop<'_> {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+
+        let _lock = LockOnDrop(&other);
+        panic!("unwinding");
+    });
+    assert!(!other.is_poisoned());
+    assert_eq!(other.into_inner().unwrap(), 1);
+
+    let mutex = Arc::try_unwrap(mutex).unwrap();
+    assert_eq!(mutex.into_inner().unwrap_err().into_inner(), [1, 2, 3]);
 }
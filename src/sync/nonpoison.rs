@@ -0,0 +1,180 @@
+//! Locks that ignore panics of their holders
+//!
+//! A panic while holding the lock releases it like any other unlock, the
+//! next holder sees whatever state the data was left in
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::{
+    mutex::{self, RawLock, RawMutex},
+    poison::{PoisonError, TryLockError},
+};
+
+/// `sync::mutex::Mutex` without poisoning, `lock` hands out the guard
+/// directly
+///
+/// A wrapper that looks through the poison flag of the inner mutex, so
+/// R picks the waiting strategy the same way
+pub struct Mutex<T: ?Sized, R: RawLock = RawMutex> {
+    inner: mutex::Mutex<T, R>,
+}
+
+/// Access to the data of a locked `Mutex`, unlocks it when dropped
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized, R: RawLock = RawMutex> {
+    guard: mutex::MutexGuard<'a, T, R>,
+}
+
+/// A `MutexGuard` narrowed to a part of the data by `MutexGuard::map`
+pub type MappedMutexGuard<'a, T, R = RawMutex> = mutex::MappedMutexGuard<'a, T, R>;
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self::with_raw(data)
+    }
+}
+
+impl<T, R: RawLock> Mutex<T, R> {
+    /// A mutex locked with the strategy R, see `mutex::Mutex::with_raw`
+    pub const fn with_raw(data: T) -> Self {
+        Self {
+            inner: mutex::Mutex::with_raw(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: ?Sized, R: RawLock> Mutex<T, R> {
+    pub fn lock(&self) -> MutexGuard<'_, T, R> {
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        MutexGuard { guard }
+    }
+
+    /// Locks the mutex if it is free, without waiting
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, R>> {
+        let guard = match self.inner.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        Some(MutexGuard { guard })
+    }
+
+    /// Mutable access without locking, the borrow proves nobody else
+    /// can hold the lock
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Default, R: RawLock> Default for Mutex<T, R> {
+    fn default() -> Self {
+        Self::with_raw(T::default())
+    }
+}
+
+impl<T, R: RawLock> From<T> for Mutex<T, R> {
+    fn from(data: T) -> Self {
+        Self::with_raw(data)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, R: RawLock> fmt::Debug for Mutex<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+impl<'a, T: ?Sized, R: RawLock> MutexGuard<'a, T, R> {
+    /// Narrows the guard to a part of the data, the mutex stays locked
+    /// until the returned guard is dropped
+    pub fn map<U: ?Sized, F>(orig: Self, f: F) -> MappedMutexGuard<'a, U, R>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        mutex::MutexGuard::map(orig.guard, f)
+    }
+}
+
+impl<T: ?Sized, R: RawLock> Deref for MutexGuard<'_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, R: RawLock> DerefMut for MutexGuard<'_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, R: RawLock> fmt::Debug for MutexGuard<'_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[test]
+fn nonpoison_test_mutex() {
+    use super::arc::Arc;
+    use std::thread;
+
+    let mutex = Arc::new(Mutex::new(vec![1]));
+
+    let result = thread::spawn({
+        let mutex = mutex.clone();
+        move || {
+            let mut data = mutex.lock();
+            data.push(2);
+            panic!("does not poison");
+        }
+    })
+    .join();
+    assert!(result.is_err());
+
+    // whatever the panicking thread left is visible
+    mutex.lock().push(3);
+    {
+        let mut first = MutexGuard::map(mutex.lock(), |data| &mut data[0]);
+        *first = 0;
+        assert!(mutex.try_lock().is_none());
+    }
+    assert_eq!(format!("{:?}", mutex), "Mutex { data: [0, 2, 3] }");
+
+    let mut mutex = Arc::try_unwrap(mutex).unwrap();
+    mutex.get_mut().push(4);
+    assert_eq!(mutex.into_inner(), [0, 2, 3, 4]);
+}
+
+#[test]
+fn nonpoison_test_raw_lock() {
+    use super::ticket_lock::RawTicketLock;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mutex: Mutex<_, RawTicketLock> = Mutex::with_raw(1);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut data = mutex.lock();
+        *data += 1;
+        panic!("does not poison");
+    }));
+    assert!(result.is_err());
+
+    *mutex.try_lock().unwrap() += 1;
+    *MutexGuard::map(mutex.lock(), |data| data) += 1;
+    assert_eq!(mutex.into_inner(), 4);
+}
@@ -0,0 +1,225 @@
+use std::{
+    cell::Cell,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::futex;
+
+const INCOMPLETE: u32 = 0;
+/// an initializer panicked, the next `call_once` panics too
+const POISONED: u32 = 1;
+const RUNNING: u32 = 2;
+/// running, and other threads sleep waiting for it
+const QUEUED: u32 = 3;
+const COMPLETE: u32 = 4;
+
+/// Runs an initializer exactly once, however many threads race for it
+///
+/// Threads calling `call_once` while another one runs its closure block
+/// until it is done. If the closure panics, the `Once` is poisoned
+/// and later `call_once` calls panic as well, `call_once_force` can
+/// still finish the initialization
+pub struct Once {
+    state: AtomicU32,
+}
+
+/// Passed to the closure of `Once::call_once_force`
+pub struct OnceState {
+    poisoned: bool,
+    /// the state the `Once` ends up in if the closure returns
+    set_state_to: Cell<u32>,
+}
+
+impl OnceState {
+    /// True if an earlier initializer panicked
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Leaves the `Once` poisoned even though the closure returns, so the
+    /// next caller runs its initializer
+    pub(crate) fn poison(&self) {
+        self.set_state_to.set(POISONED);
+    }
+}
+
+/// Sets the state when the initializer returns or unwinds, waking the
+/// threads waiting for it
+struct CompletionGuard<'a> {
+    state: &'a AtomicU32,
+    set_state_on_drop_to: u32,
+}
+
+impl Drop for CompletionGuard<'_> {
+    fn drop(&mut self) {
+        // synchronizes-with the acquire loads of the waiters
+        if self
+            .state
+            .swap(self.set_state_on_drop_to, Ordering::Release)
+            == QUEUED
+        {
+            futex::wake_all(self.state);
+        }
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// True once an initializer returned
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Runs f if no initializer completed yet, blocking while another
+    /// thread runs one
+    ///
+    /// # Panics
+    ///
+    /// If the `Once` is poisoned, or when f panics
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // fast path, without the dyn call
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = Some(f);
+        self.call(false, &mut |_| f.take().unwrap()());
+    }
+
+    /// `call_once` that runs f on a poisoned `Once` as well, f is told
+    /// through `OnceState::is_poisoned`
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = Some(f);
+        self.call(true, &mut |state| f.take().unwrap()(state));
+    }
+
+    #[cold]
+    fn call(&self, ignore_poison: bool, f: &mut dyn FnMut(&OnceState)) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state {
+                POISONED if !ignore_poison => {
+                    panic!("Once instance has previously been poisoned");
+                }
+                INCOMPLETE | POISONED => {
+                    if let Err(cur) = self.state.compare_exchange_weak(
+                        state,
+                        RUNNING,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        state = cur;
+                        continue;
+                    }
+
+                    // poisons if f unwinds
+                    let mut guard = CompletionGuard {
+                        state: &self.state,
+                        set_state_on_drop_to: POISONED,
+                    };
+                    let once_state = OnceState {
+                        poisoned: state == POISONED,
+                        set_state_to: Cell::new(COMPLETE),
+                    };
+                    f(&once_state);
+                    guard.set_state_on_drop_to = once_state.set_state_to.get();
+                    return;
+                }
+                RUNNING | QUEUED => {
+                    // tell the running thread to wake us
+                    if state == RUNNING {
+                        if let Err(cur) = self.state.compare_exchange_weak(
+                            RUNNING,
+                            QUEUED,
+                            Ordering::Relaxed,
+                            Ordering::Acquire,
+                        ) {
+                            state = cur;
+                            continue;
+                        }
+                    }
+                    futex::wait(&self.state, QUEUED);
+                    state = self.state.load(Ordering::Acquire);
+                }
+                COMPLETE => return,
+                _ => unreachable!("invalid Once state"),
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for OnceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceState")
+            .field("poisoned", &self.poisoned)
+            .finish()
+    }
+}
+
+#[test]
+fn once_test_race() {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    static INIT: Once = Once::new();
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..16 {
+            s.spawn(|| {
+                INIT.call_once(|| {
+                    // give the others time to pile up behind us
+                    thread::yield_now();
+                    CALLS.fetch_add(1, Ordering::Relaxed);
+                });
+                // everybody returns after the initializer is done
+                assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+                assert!(INIT.is_completed());
+            });
+        }
+    });
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn once_test_poison() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let once = Once::new();
+
+    let result = catch_unwind(AssertUnwindSafe(|| once.call_once(|| panic!("init"))));
+    assert!(result.is_err());
+    assert!(!once.is_completed());
+
+    let result = catch_unwind(AssertUnwindSafe(|| once.call_once(|| {})));
+    assert!(result.is_err());
+
+    let mut poisoned = false;
+    once.call_once_force(|state| poisoned = state.is_poisoned());
+    assert!(poisoned && once.is_completed());
+
+    // done for good
+    once.call_once(|| unreachable!());
+    once.call_once_force(|_| unreachable!());
+}
@@ -0,0 +1,243 @@
+use std::{
+    cell::UnsafeCell,
+    convert::Infallible,
+    fmt,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    panic::{RefUnwindSafe, UnwindSafe},
+};
+
+use super::once::Once;
+
+/// A cell written once, usable from many threads
+///
+/// The first `get_or_init` or `set` stores the value, later ones hand
+/// out the stored value. Racing initializers block until the winner is
+/// done. An initializer that panics leaves the cell empty for the next
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+    // we drop a T
+    phantom: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for OnceLock<T> {}
+// a T set on one thread can be taken on another
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+impl<T: UnwindSafe> UnwindSafe for OnceLock<T> {}
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        OnceLock {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The value, if it is initialized already
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { (*self.value.get()).assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Stores value if the cell is empty, otherwise hands it back
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// The value, initialized by f if the cell is empty
+    ///
+    /// Blocks while another thread initializes the cell. Calling it again
+    /// from f deadlocks
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.get_or_try_init(|| Ok::<T, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// `get_or_init` with an initializer that may fail, the cell stays
+    /// empty then
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        self.initialize(f)?;
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    #[cold]
+    fn initialize<F, E>(&self, f: F) -> Result<(), E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let mut result = Ok(());
+        // a panicking f poisons the once, the next caller tries again
+        self.once.call_once_force(|state| match f() {
+            Ok(value) => unsafe {
+                (*self.value.get()).write(value);
+            },
+            Err(err) => {
+                result = Err(err);
+                state.poison();
+            }
+        });
+        result
+    }
+
+    /// Empties the cell, handing out the value if there was one
+    pub fn take(&mut self) -> Option<T> {
+        mem::take(self).into_inner()
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        let value = if self.once.is_completed() {
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        };
+        // the value moved out
+        mem::forget(self);
+        value
+    }
+
+    /// # Safety
+    ///
+    /// The cell has to be initialized
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.value.get()).assume_init_ref()
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        let cell = Self::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T: Clone> Clone for OnceLock<T> {
+    fn clone(&self) -> Self {
+        match self.get() {
+            Some(value) => Self::from(value.clone()),
+            None => Self::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for OnceLock<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<T: Eq> Eq for OnceLock<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("OnceLock");
+        match self.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+#[test]
+fn once_lock_test_race() {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    let cell = OnceLock::new();
+    let calls = AtomicUsize::new(0);
+
+    let seen: std::vec::Vec<_> = thread::scope(|s| {
+        let handles: std::vec::Vec<_> = (0..16)
+            .map(|i| {
+                let (cell, calls) = (&cell, &calls);
+                s.spawn(move || {
+                    let value: &String = cell.get_or_init(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        thread::yield_now();
+                        format!("thread {}", i)
+                    });
+                    value as *const String as usize
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // all threads see the one value of the winner
+    let first = cell.get().unwrap() as *const String as usize;
+    assert!(seen.iter().all(|&value| value == first));
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    assert!(cell.get().unwrap().starts_with("thread "));
+}
+
+#[test]
+fn once_lock_test_api() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut cell = OnceLock::new();
+    assert_eq!(cell.get(), None);
+    assert_eq!(format!("{:?}", cell), "OnceLock(<uninit>)");
+
+    // failing and panicking initializers leave it empty
+    assert_eq!(cell.get_or_try_init(|| Err("no")), Err("no"));
+    let result = catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("init"))));
+    assert!(result.is_err());
+    assert_eq!(cell.get(), None);
+
+    assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(vec![1])), Ok(&vec![1]));
+    assert_eq!(cell.set(vec![2]), Err(vec![2]));
+    assert_eq!(cell.get_or_init(|| vec![3]), &[1]);
+    cell.get_mut().unwrap().push(4);
+    assert_eq!(cell.clone(), OnceLock::from(vec![1, 4]));
+    assert_eq!(format!("{:?}", cell), "OnceLock([1, 4])");
+
+    assert_eq!(cell.take(), Some(vec![1, 4]));
+    assert_eq!(cell.take(), None);
+    assert_eq!(cell.set(vec![5]), Ok(()));
+    assert_eq!(cell.into_inner(), Some(vec![5]));
+}
@@ -0,0 +1,610 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::{
+    futex,
+    poison::{self, LockResult, TryLockError, TryLockResult},
+};
+
+// layout of the state word, readers are counted in the low bits
+const READER: u32 = 1;
+const READERS: u32 = UPGRADABLE - 1;
+/// held by the one upgradable reader, shares the lock with plain readers
+const UPGRADABLE: u32 = 1 << 29;
+const WRITER: u32 = 1 << 30;
+
+/// Spins before going to sleep
+const SPIN_LIMIT: usize = 100;
+
+/// Reader-writer lock protecting a value of T
+///
+/// Any number of readers or a single writer hold the lock at a time.
+/// Writers are preferred: once a writer waits, new readers wait behind it,
+/// so a steady stream of readers can't starve writers. A thread trying to
+/// read lock twice can deadlock against a waiting writer because of that
+///
+/// An upgradable read shares the lock with readers, but excludes writers
+/// and other upgradable reads, so it can become a write lock without
+/// letting another writer in between
+///
+/// Poisoned if a writer panics, like `Mutex`
+pub struct RwLock<T: ?Sized> {
+    state: AtomicU32,
+    /// writers (and upgrades) waiting for the lock
+    writers_waiting: AtomicU32,
+    /// bumped when the lock is released in a way that may let a waiter in
+    seq: AtomicU32,
+    /// threads sleeping on seq
+    sleepers: AtomicU32,
+    poison: poison::Flag,
+    data: UnsafeCell<T>,
+}
+
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    phantom: PhantomData<*const ()>,
+}
+
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockUpgradableReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    phantom: PhantomData<*const ()>,
+}
+
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    poison: poison::Guard,
+    phantom: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockUpgradableReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+// a panic while holding the lock is reported through poisoning
+impl<T: ?Sized> UnwindSafe for RwLock<T> {}
+impl<T: ?Sized> RefUnwindSafe for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        RwLock {
+            state: AtomicU32::new(0),
+            writers_waiting: AtomicU32::new(0),
+            seq: AtomicU32::new(0),
+            sleepers: AtomicU32::new(0),
+            poison: poison::Flag::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(poison::PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Takes the lock with a CAS from state to f(state), as long as f
+    /// allows it. acquire synchronizes-with the release of the last holder
+    fn try_acquire(&self, f: impl Fn(u32) -> Option<u32>) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let new = match f(state) {
+                Some(new) => new,
+                None => return false,
+            };
+            match self
+                .state
+                .compare_exchange_weak(state, new, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(cur) => state = cur,
+            }
+        }
+    }
+
+    fn read_state(&self, state: u32) -> Option<u32> {
+        // waiting writers go first
+        if state & WRITER != 0 || self.writers_waiting.load(Ordering::Relaxed) != 0 {
+            return None;
+        }
+        if state & READERS == READERS {
+            panic!("too many readers");
+        }
+        Some(state + READER)
+    }
+
+    fn upgradable_state(&self, state: u32) -> Option<u32> {
+        if state & UPGRADABLE != 0 {
+            return None;
+        }
+        self.read_state(state).map(|_| state | UPGRADABLE)
+    }
+
+    fn write_state(state: u32) -> Option<u32> {
+        if state == 0 {
+            Some(WRITER)
+        } else {
+            None
+        }
+    }
+
+    /// Blocks until try_acquire succeeds
+    #[cold]
+    fn wait_until(&self, try_acquire: impl Fn() -> bool) {
+        for _ in 0..SPIN_LIMIT {
+            if try_acquire() {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if try_acquire() {
+                return;
+            }
+
+            // register before the last check, a release from now on either
+            // sees us sleeping or changes seq under the futex
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            if try_acquire() {
+                self.sleepers.fetch_sub(1, Ordering::Relaxed);
+                return;
+            }
+            futex::wait(&self.seq, seq);
+            self.sleepers.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Called after a release that may let waiters in
+    fn wake_waiters(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            futex::wake_all(&self.seq);
+        }
+    }
+
+    /// Waits for the write lock, marking us as a waiting writer so no new
+    /// reader gets in
+    fn wait_as_writer(&self, try_acquire: impl Fn() -> bool) {
+        self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+        self.wait_until(try_acquire);
+        // readers held back by us are woken when we unlock
+        self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Blocks until the lock can be shared with other readers
+    ///
+    /// Read locks don't poison, but fail if a writer poisoned the lock
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let read = || self.try_acquire(|state| self.read_state(state));
+        if !read() {
+            self.wait_until(read);
+        }
+        self.poison.guard(RwLockReadGuard {
+            lock: self,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if self.try_acquire(|state| self.read_state(state)) {
+            Ok(self.poison.guard(RwLockReadGuard {
+                lock: self,
+                phantom: PhantomData,
+            })?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks until the lock can be shared with plain readers, only one
+    /// upgradable read is held at a time
+    pub fn upgradable_read(&self) -> LockResult<RwLockUpgradableReadGuard<'_, T>> {
+        let read = || self.try_acquire(|state| self.upgradable_state(state));
+        if !read() {
+            self.wait_until(read);
+        }
+        self.poison.guard(RwLockUpgradableReadGuard {
+            lock: self,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn try_upgradable_read(&self) -> TryLockResult<RwLockUpgradableReadGuard<'_, T>> {
+        if self.try_acquire(|state| self.upgradable_state(state)) {
+            Ok(self.poison.guard(RwLockUpgradableReadGuard {
+                lock: self,
+                phantom: PhantomData,
+            })?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks until the lock is free
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let write = || self.try_acquire(Self::write_state);
+        if !write() {
+            self.wait_as_writer(write);
+        }
+        unsafe { RwLockWriteGuard::new(self) }
+    }
+
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        if self.try_acquire(Self::write_state) {
+            Ok(unsafe { RwLockWriteGuard::new(self) }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// True if a writer panicked while holding the lock
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Marks the data as consistent again after a panic
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Mutable access without locking, the borrow proves nobody else
+    /// can hold the lock
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        self.poison.guard(data)
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.poison.get());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let state = self.lock.state.fetch_sub(READER, Ordering::Release);
+        // only writers and upgrades wait for the readers to leave
+        if state & READERS == READER {
+            self.lock.wake_waiters();
+        }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockUpgradableReadGuard<'a, T> {
+    /// Turns the read into a write lock, waiting for the other readers
+    /// to leave. No writer can get in between
+    pub fn upgrade(this: Self) -> RwLockWriteGuard<'a, T> {
+        let lock = this.lock;
+        std::mem::forget(this);
+
+        let upgrade = || lock.try_acquire(|state| (state == UPGRADABLE).then_some(WRITER));
+        if !upgrade() {
+            lock.wait_as_writer(upgrade);
+        }
+        // already checked for poison when locking
+        unsafe { RwLockWriteGuard::new(lock) }.unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Turns the read into a write lock if no other reader holds the lock
+    pub fn try_upgrade(this: Self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        if this
+            .lock
+            .try_acquire(|state| (state == UPGRADABLE).then_some(WRITER))
+        {
+            let lock = this.lock;
+            std::mem::forget(this);
+            Ok(unsafe { RwLockWriteGuard::new(lock) }.unwrap_or_else(|err| err.into_inner()))
+        } else {
+            Err(this)
+        }
+    }
+
+    /// Gives up the right to upgrade, keeping a plain read lock
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let lock = this.lock;
+        std::mem::forget(this);
+
+        lock.state.fetch_sub(UPGRADABLE - READER, Ordering::Release);
+        lock.wake_waiters();
+        RwLockReadGuard {
+            lock,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockUpgradableReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockUpgradableReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(UPGRADABLE, Ordering::Release);
+        self.lock.wake_waiters();
+    }
+}
+
+impl<'a, T: ?Sized> RwLockWriteGuard<'a, T> {
+    /// # Safety
+    ///
+    /// lock has to be write locked by the caller
+    unsafe fn new(lock: &'a RwLock<T>) -> LockResult<RwLockWriteGuard<'a, T>> {
+        lock.poison.guard(RwLockWriteGuard {
+            lock,
+            poison: lock.poison.track(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Turns the write into a read lock, letting other readers in
+    /// without a writer getting in between
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let lock = this.lock;
+        lock.poison.done(&this.poison);
+        std::mem::forget(this);
+
+        lock.state.store(READER, Ordering::Release);
+        lock.wake_waiters();
+        RwLockReadGuard {
+            lock,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
+        // nobody else touches the state while it is write locked
+        self.lock.state.store(0, Ordering::Release);
+        self.lock.wake_waiters();
+    }
+}
+
+macro_rules! guard_fmt {
+    ($($guard:ident),*) => {$(
+        impl<T: ?Sized + fmt::Debug> fmt::Debug for $guard<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&**self, f)
+            }
+        }
+    )*};
+}
+
+guard_fmt!(RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard);
+
+#[test]
+fn rwlock_test_guards() {
+    let lock = RwLock::new(1);
+
+    let r1 = lock.read().unwrap();
+    let r2 = lock.try_read().unwrap();
+    assert_eq!(*r1 + *r2, 2);
+    assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+    drop((r1, r2));
+
+    let mut w = lock.write().unwrap();
+    *w += 1;
+    assert!(lock.try_read().is_err());
+    assert_eq!(
+        format!("{:?}", lock),
+        "RwLock { data: <locked>, poisoned: false, .. }"
+    );
+
+    // downgrading lets readers in, never a writer
+    let r = RwLockWriteGuard::downgrade(w);
+    assert_eq!(*lock.try_read().unwrap(), 2);
+    assert!(lock.try_write().is_err());
+    drop(r);
+
+    let up = lock.upgradable_read().unwrap();
+    let r = lock.read().unwrap();
+    assert!(lock.try_upgradable_read().is_err());
+    assert!(lock.try_write().is_err());
+
+    // other readers block the upgrade
+    let up = RwLockUpgradableReadGuard::try_upgrade(up).unwrap_err();
+    drop(r);
+    let mut w = RwLockUpgradableReadGuard::try_upgrade(up).unwrap();
+    *w *= 10;
+    drop(w);
+
+    let up = lock.try_upgradable_read().unwrap();
+    let r = RwLockUpgradableReadGuard::downgrade(up);
+    assert!(lock.try_upgradable_read().is_ok());
+    drop(r);
+
+    let mut lock = lock;
+    *lock.get_mut().unwrap() += 1;
+    assert_eq!(lock.into_inner().unwrap(), 21);
+}
+
+#[test]
+fn rwlock_test_writer_preference() {
+    use std::{thread, time::Duration};
+
+    let lock = RwLock::new(0);
+
+    thread::scope(|s| {
+        let r = lock.read().unwrap();
+        let writer = s.spawn(|| *lock.write().unwrap() += 1);
+
+        // once the writer waits, new readers queue behind it
+        while lock.writers_waiting.load(Ordering::Relaxed) == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        let reader = s.spawn(|| *lock.read().unwrap());
+
+        drop(r);
+        writer.join().unwrap();
+        assert_eq!(reader.join().unwrap(), 1);
+    });
+
+    // a blocked upgrade gets in before other writers
+    thread::scope(|s| {
+        let r = lock.read().unwrap();
+        let upgrade = s.spawn(|| {
+            let up = lock.upgradable_read().unwrap();
+            let mut w = RwLockUpgradableReadGuard::upgrade(up);
+            *w += 1;
+        });
+        while lock.state.load(Ordering::Relaxed) & UPGRADABLE == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let writer = s.spawn(|| *lock.write().unwrap() *= 10);
+
+        while lock.writers_waiting.load(Ordering::Relaxed) < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(r);
+        upgrade.join().unwrap();
+        writer.join().unwrap();
+    });
+    assert_eq!(*lock.read().unwrap(), 20);
+}
+
+#[test]
+fn rwlock_test_poison() {
+    use super::arc::Arc;
+    use std::thread;
+
+    let lock = Arc::new(RwLock::new(0));
+
+    // panicking readers don't poison
+    let result = thread::spawn({
+        let lock = lock.clone();
+        move || {
+            let _r = lock.read().unwrap();
+            panic!("reader");
+        }
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(!lock.is_poisoned());
+
+    let result = thread::spawn({
+        let lock = lock.clone();
+        move || {
+            let mut w = lock.write().unwrap();
+            *w = 1;
+            panic!("writer");
+        }
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(lock.is_poisoned());
+    assert_eq!(*lock.read().unwrap_err().into_inner(), 1);
+    assert!(matches!(lock.try_write(), Err(TryLockError::Poisoned(_))));
+
+    lock.clear_poison();
+    assert_eq!(*lock.read().unwrap(), 1);
+}
+
+#[test]
+fn rwlock_test_stress() {
+    use std::thread;
+
+    // writers keep both halves equal, readers must never see them differ
+    let lock = RwLock::new((0u64, 0u64));
+
+    thread::scope(|s| {
+        for _ in 0..6 {
+            s.spawn(|| {
+                for i in 0..2000 {
+                    let pair = lock.read().unwrap();
+                    assert_eq!(pair.0, pair.1);
+                    if i % 64 == 0 {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                for i in 0..500 {
+                    if i % 2 == 0 {
+                        let mut pair = lock.write().unwrap();
+                        pair.0 += 1;
+                        thread::yield_now();
+                        pair.1 += 1;
+                    } else {
+                        let up = lock.upgradable_read().unwrap();
+                        assert_eq!(up.0, up.1);
+                        let mut pair = RwLockUpgradableReadGuard::upgrade(up);
+                        pair.0 += 1;
+                        pair.1 += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(lock.into_inner().unwrap(), (1000, 1000));
+}
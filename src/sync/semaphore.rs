@@ -0,0 +1,234 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::futex;
+
+/// Counting semaphore, hands out a limited number of permits
+///
+/// `acquire` blocks until enough permits are free, the returned
+/// `SemaphorePermit` gives them back when dropped. Counts are u32, the
+/// width of the futex the waiters sleep on
+pub struct Semaphore {
+    permits: AtomicU32,
+    /// threads sleeping on permits
+    waiters: AtomicU32,
+}
+
+/// Permits taken from a `Semaphore`, released when dropped
+#[must_use = "if unused the permits are released right away"]
+pub struct SemaphorePermit<'a> {
+    sem: &'a Semaphore,
+    permits: u32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Semaphore {
+            permits: AtomicU32::new(permits),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Free permits right now, may be outdated by the time it returns
+    pub fn available_permits(&self) -> u32 {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until a permit is free
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Blocks until n permits are free, and takes them all at once
+    pub fn acquire_many(&self, n: u32) -> SemaphorePermit<'_> {
+        loop {
+            if let Some(permit) = self.try_acquire_many(n) {
+                return permit;
+            }
+
+            // register before the last check, a release from now on
+            // either sees us waiting or changes permits under the futex
+            self.waiters.fetch_add(1, Ordering::SeqCst);
+            let permits = self.permits.load(Ordering::SeqCst);
+            if permits < n {
+                futex::wait(&self.permits, permits);
+            }
+            self.waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Takes n permits if they are free, without waiting
+    pub fn try_acquire_many(&self, n: u32) -> Option<SemaphorePermit<'_>> {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        while permits >= n {
+            // synchronizes-with the release in `release`
+            match self.permits.compare_exchange_weak(
+                permits,
+                permits - n,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(SemaphorePermit {
+                        sem: self,
+                        permits: n,
+                    })
+                }
+                Err(cur) => permits = cur,
+            }
+        }
+        None
+    }
+
+    /// Adds n permits, also used to hand back permits that were forgotten
+    ///
+    /// # Panics
+    ///
+    /// If the count would overflow, the count is left as it was then
+    pub fn release(&self, n: u32) {
+        self.add_permits(|permits| {
+            permits
+                .checked_add(n)
+                .expect("semaphore permit count overflowed")
+        });
+    }
+
+    /// Replaces the count by f of it and wakes the waiters, f checks the
+    /// count before anything is stored
+    fn add_permits(&self, f: impl Fn(u32) -> u32) {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        loop {
+            // seqcst, so the waiters check below can't miss a thread
+            // registering in `acquire_many`
+            match self.permits.compare_exchange_weak(
+                permits,
+                f(permits),
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(cur) => permits = cur,
+            }
+        }
+
+        // waiters may want different counts, let all of them check
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            futex::wake_all(&self.permits);
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+impl SemaphorePermit<'_> {
+    pub fn num_permits(&self) -> u32 {
+        self.permits
+    }
+
+    /// Keeps the permits taken, `Semaphore::release` hands them back
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        // only extra `release`s can push the count that far, clamp it
+        // rather than panicking in drop
+        let n = self.permits;
+        self.sem.add_permits(|permits| permits.saturating_add(n));
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn semaphore_test_limit() {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    // never more than 3 workers inside at once
+    let sem = Semaphore::new(3);
+    let inside = AtomicUsize::new(0);
+    let max_inside = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..100 {
+                    let _permit = sem.acquire();
+                    let now = inside.fetch_add(1, Ordering::Relaxed) + 1;
+                    max_inside.fetch_max(now, Ordering::Relaxed);
+                    thread::yield_now();
+                    inside.fetch_sub(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert!(max_inside.load(Ordering::Relaxed) <= 3);
+    assert_eq!(sem.available_permits(), 3);
+}
+
+#[test]
+fn semaphore_test_many() {
+    use std::thread;
+
+    let sem = Semaphore::new(4);
+
+    let three = sem.acquire_many(3);
+    assert_eq!(three.num_permits(), 3);
+    assert!(sem.try_acquire_many(2).is_none());
+    let one = sem.try_acquire().unwrap();
+    assert!(sem.try_acquire().is_none());
+    assert_eq!(format!("{:?}", sem), "Semaphore { permits: 0 }");
+
+    // the waiter needs both permits back
+    thread::scope(|s| {
+        let waiter = s.spawn(|| sem.acquire_many(2).num_permits());
+        drop(one);
+        thread::yield_now();
+        drop(three);
+        assert_eq!(waiter.join().unwrap(), 2);
+    });
+    assert_eq!(sem.available_permits(), 4);
+
+    sem.acquire_many(4).forget();
+    assert_eq!(sem.available_permits(), 0);
+    sem.release(4);
+    assert!(sem.try_acquire_many(4).is_some());
+}
+
+#[test]
+fn semaphore_test_overflow() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let sem = Semaphore::new(u32::MAX - 1);
+    let permit = sem.acquire_many(2);
+
+    // a release that doesn't fit leaves the count alone
+    sem.release(2);
+    let result = catch_unwind(AssertUnwindSafe(|| sem.release(2)));
+    assert!(result.is_err());
+    assert_eq!(sem.available_permits(), u32::MAX - 1);
+
+    // dropping a permit never panics
+    drop(permit);
+    assert_eq!(sem.available_permits(), u32::MAX);
+}
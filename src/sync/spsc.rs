@@ -0,0 +1,367 @@
+use std::{
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::collection::{deque::wrap_idx, raw::raw_vec::RawVec};
+
+use super::arc::Arc;
+
+/// Shared ring buffer of a single producer single consumer queue
+///
+/// Uses the same power of two buffer as `Deque`, one slot is always kept
+/// free so that `head == tail` means empty and `wrap(tail + 1) == head` full
+struct Inner<T> {
+    buf: RawVec<T>,
+    /// slots in the ring, the buffer's capacity unless T is zero sized
+    cap: usize,
+    /// next slot to read, only written by the consumer
+    head: AtomicUsize,
+    /// next slot to write, only written by the producer
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn cap(&self) -> usize {
+        self.cap
+    }
+
+    fn ptr(&self) -> *mut T {
+        self.buf.ptr.as_ptr()
+    }
+
+    fn next(&self, idx: usize) -> usize {
+        wrap_idx(idx + 1, self.cap())
+    }
+
+    fn len(&self, head: usize, tail: usize) -> usize {
+        wrap_idx(tail.wrapping_sub(head).wrapping_add(self.cap()), self.cap())
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // both halves are gone, drop what is left between head and tail
+        let mut head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        while head != tail {
+            unsafe { ptr::drop_in_place(self.ptr().add(head)) }
+            head = self.next(head);
+        }
+    }
+}
+
+/// Writing half of a spsc queue
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// Reading half of a spsc queue
+pub struct Consumer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// Creates a wait-free single producer single consumer queue
+/// holding at least capacity elements
+///
+/// Zero sized elements take no memory, but are counted against the same
+/// power of two capacity a sized element type would get
+///
+/// # Panics
+///
+/// If the capacity plus the spare slot, rounded up to a power of two,
+/// overflows usize
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    // one more slot for the full/empty distinction
+    let cap = capacity
+        .checked_add(1)
+        .and_then(usize::checked_next_power_of_two)
+        .expect("spsc capacity overflow");
+    // RawVec reports usize::MAX for zero sized types, the ring keeps cap
+    let buf = RawVec::with_capacity(cap);
+
+    let inner = Arc::new(Inner {
+        buf,
+        cap,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+
+    (
+        Producer {
+            inner: inner.clone(),
+        },
+        Consumer { inner },
+    )
+}
+
+impl<T> Producer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.cap() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.inner.tail.load(Ordering::Relaxed);
+        let head = self.inner.head.load(Ordering::Acquire);
+        self.inner.len(head, tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Pushes value to the queue, returns it back if the queue is full
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let inner = &*self.inner;
+        let tail = inner.tail.load(Ordering::Relaxed);
+        let next = inner.next(tail);
+
+        // synchronizes-with release-store of head in `Consumer`,
+        // the slot at tail is not read anymore
+        if next == inner.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+
+        unsafe { ptr::write(inner.ptr().add(tail), value) }
+
+        // publish the written slot to the consumer
+        inner.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Pushes as many elements of values as fit, returns how many were pushed
+    pub fn push_slice(&mut self, values: &[T]) -> usize {
+        let inner = &*self.inner;
+        let cap = inner.cap();
+        let tail = inner.tail.load(Ordering::Relaxed);
+        let head = inner.head.load(Ordering::Acquire);
+
+        let free = cap - 1 - inner.len(head, tail);
+        let n = free.min(values.len());
+
+        // the free region may wrap around the end of the buffer
+        let first = n.min(cap - tail);
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), inner.ptr().add(tail), first);
+            ptr::copy_nonoverlapping(values.as_ptr().add(first), inner.ptr(), n - first);
+        }
+
+        inner.tail.store(wrap_idx(tail + n, cap), Ordering::Release);
+        n
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.cap() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.inner.head.load(Ordering::Relaxed);
+        let tail = self.inner.tail.load(Ordering::Acquire);
+        self.inner.len(head, tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pops the oldest element, None if the queue is empty
+    pub fn pop(&mut self) -> Option<T> {
+        let inner = &*self.inner;
+        let head = inner.head.load(Ordering::Relaxed);
+
+        // synchronizes-with release-store of tail in `Producer`,
+        // the slot at head is fully written
+        if head == inner.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { ptr::read(inner.ptr().add(head)) };
+
+        // hand the slot back to the producer
+        inner.head.store(inner.next(head), Ordering::Release);
+        Some(value)
+    }
+
+    /// Returns a reference to the oldest element without popping it
+    pub fn peek(&self) -> Option<&T> {
+        let inner = &*self.inner;
+        let head = inner.head.load(Ordering::Relaxed);
+        if head == inner.tail.load(Ordering::Acquire) {
+            None
+        } else {
+            unsafe { Some(&*inner.ptr().add(head)) }
+        }
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Pops elements into out until it is full or the queue is empty,
+    /// returns how many were popped
+    pub fn pop_slice(&mut self, out: &mut [T]) -> usize {
+        let inner = &*self.inner;
+        let cap = inner.cap();
+        let head = inner.head.load(Ordering::Relaxed);
+        let tail = inner.tail.load(Ordering::Acquire);
+
+        let n = inner.len(head, tail).min(out.len());
+
+        let first = n.min(cap - head);
+        unsafe {
+            ptr::copy_nonoverlapping(inner.ptr().add(head), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(inner.ptr(), out.as_mut_ptr().add(first), n - first);
+        }
+
+        inner.head.store(wrap_idx(head + n, cap), Ordering::Release);
+        n
+    }
+}
+
+impl<T> Iterator for Consumer<T> {
+    type Item = T;
+
+    /// Non blocking, ends as soon as the queue is empty
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[test]
+fn spsc_test_push_pop() {
+    let (mut tx, mut rx) = channel(3);
+    assert_eq!(tx.capacity(), 3);
+    assert_eq!(rx.pop(), None);
+
+    for round in 0..10 {
+        assert_eq!(tx.push(round), Ok(()));
+        assert_eq!(tx.push(round + 1), Ok(()));
+        assert_eq!(tx.push(round + 2), Ok(()));
+        assert_eq!(tx.push(round + 3), Err(round + 3));
+        assert!(tx.is_full());
+        assert_eq!(rx.len(), 3);
+        assert_eq!(rx.peek(), Some(&round));
+        assert_eq!(rx.pop(), Some(round));
+        assert_eq!(rx.pop(), Some(round + 1));
+        assert_eq!(rx.pop(), Some(round + 2));
+        assert!(rx.is_empty());
+    }
+}
+
+#[test]
+fn spsc_test_slice_wrapped() {
+    let (mut tx, mut rx) = channel::<u32>(7);
+    let mut out = [0; 8];
+
+    // move head and tail close to the end of the buffer
+    assert_eq!(tx.push_slice(&[0; 5]), 5);
+    assert_eq!(rx.pop_slice(&mut out[..5]), 5);
+
+    assert_eq!(tx.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]), 7);
+    assert_eq!(tx.push_slice(&[10]), 0);
+    assert_eq!(rx.pop_slice(&mut out), 7);
+    assert_eq!(out[..7], [1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(rx.pop_slice(&mut out), 0);
+}
+
+#[test]
+fn spsc_test_zero_sized() {
+    use std::panic::catch_unwind;
+
+    let (mut tx, mut rx) = channel(3);
+    assert_eq!(tx.capacity(), 3);
+    for _ in 0..3 {
+        assert_eq!(tx.push(()), Ok(()));
+    }
+    assert_eq!(tx.push(()), Err(()));
+    assert_eq!(rx.len(), 3);
+    assert_eq!(rx.pop(), Some(()));
+    assert_eq!(tx.push_slice(&[(); 4]), 1);
+    assert_eq!(rx.pop_slice(&mut [(); 8]), 3);
+    assert!(rx.is_empty());
+
+    assert_eq!(channel::<()>(5).0.capacity(), 7);
+    assert!(catch_unwind(|| channel::<()>(usize::MAX)).is_err());
+    assert!(catch_unwind(|| channel::<u8>(usize::MAX)).is_err());
+    // fits usize, but its power of two doesn't
+    assert!(catch_unwind(|| channel::<u8>((1 << 63) + 5)).is_err());
+    assert!(catch_unwind(|| channel::<()>((1 << 63) + 5)).is_err());
+}
+
+#[test]
+fn spsc_test_drop_remaining() {
+    use super::arc::DropCounter;
+
+    let drops = DropCounter::count();
+
+    let (mut tx, mut rx) = channel(4);
+    for _ in 0..4 {
+        assert!(tx.push(DropCounter(drops.clone())).is_ok());
+    }
+    drop(rx.pop());
+    drop(tx);
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+    drop(rx);
+    assert_eq!(drops.load(Ordering::Relaxed), 4);
+}
+
+#[test]
+fn spsc_test_threaded_stress() {
+    use std::thread;
+    const N: u64 = 1_000_000;
+
+    let (mut tx, mut rx) = channel(64);
+
+    let producer = thread::spawn(move || {
+        let mut next = 0;
+        while next < N {
+            // alternate single pushes and batches
+            if next % 3 == 0 {
+                if tx.push(next).is_ok() {
+                    next += 1;
+                }
+            } else {
+                let batch: std::vec::Vec<u64> = (next..N.min(next + 10)).collect();
+                next += tx.push_slice(&batch) as u64;
+            }
+            if tx.is_full() {
+                thread::yield_now();
+            }
+        }
+    });
+
+    let consumer = thread::spawn(move || {
+        let mut expected = 0;
+        let mut buf = [0; 16];
+        while expected < N {
+            if expected % 2 == 0 {
+                if let Some(v) = rx.pop() {
+                    assert_eq!(v, expected);
+                    expected += 1;
+                }
+            } else {
+                let n = rx.pop_slice(&mut buf);
+                for &v in &buf[..n] {
+                    assert_eq!(v, expected);
+                    expected += 1;
+                }
+            }
+            if rx.is_empty() {
+                thread::yield_now();
+            }
+        }
+        assert!(rx.is_empty());
+    });
+
+    producer.join().unwrap();
+    consumer.join().unwrap();
+}
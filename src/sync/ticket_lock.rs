@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::mutex::{spin_while, Mutex, MutexGuard, RawLock};
+
+/// `Mutex` handing out the lock in the order threads asked for it
+pub type TicketLock<T> = Mutex<T, RawTicketLock>;
+
+pub type TicketLockGuard<'a, T> = MutexGuard<'a, T, RawTicketLock>;
+
+/// Fair spin lock, like the queue at a deli counter
+///
+/// Every locker draws the next ticket and waits until it is served, so
+/// nobody waits forever while others get the lock again and again. All
+/// waiters spin on the same counter, which gets expensive with many cores
+pub struct RawTicketLock {
+    next: AtomicU32,
+    serving: AtomicU32,
+}
+
+unsafe impl RawLock for RawTicketLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawTicketLock {
+        next: AtomicU32::new(0),
+        serving: AtomicU32::new(0),
+    };
+
+    fn lock(&self) {
+        // wraps around, which is fine with less than 2^32 waiters
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        // synchronizes-with the release in `unlock`
+        spin_while(|| self.serving.load(Ordering::Acquire) != ticket);
+    }
+
+    fn try_lock(&self) -> bool {
+        // only draw a ticket if it is served right away
+        let serving = self.serving.load(Ordering::Acquire);
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        // only the holder writes serving
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+    }
+}
+
+#[test]
+fn ticket_lock_test_counter() {
+    use std::thread;
+
+    let lock = TicketLock::with_raw(0);
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *lock.lock().unwrap() += 1;
+                }
+            });
+        }
+    });
+    assert_eq!(lock.into_inner().unwrap(), 4000);
+
+    let lock = TicketLock::from(());
+    let guard = lock.lock().unwrap();
+    assert!(lock.try_lock().is_err());
+    drop(guard);
+    assert!(lock.try_lock().is_ok());
+}
+
+#[test]
+fn ticket_lock_test_fifo() {
+    use super::nonpoison;
+    use std::thread;
+
+    let raw = RawTicketLock::INIT;
+    let order = nonpoison::Mutex::new(std::vec::Vec::new());
+
+    raw.lock();
+    thread::scope(|s| {
+        // queue up the threads one after the other
+        for id in 0..4 {
+            let (raw, order) = (&raw, &order);
+            s.spawn(move || {
+                raw.lock();
+                order.lock().push(id);
+                unsafe { raw.unlock() };
+            });
+            while raw.next.load(Ordering::Relaxed) != id + 2 {
+                thread::yield_now();
+            }
+        }
+        unsafe { raw.unlock() };
+    });
+    assert_eq!(order.into_inner(), [0, 1, 2, 3]);
+}
@@ -0,0 +1,142 @@
+//! A blocking bounded queue built from the sync primitives of the crate
+
+use std::{thread, time::Duration};
+
+use collection::{
+    collection::deque::Deque,
+    sync::{arc::Arc, condvar::Condvar, mutex::Mutex},
+};
+
+/// Multi producer multi consumer queue, `push` blocks while full and
+/// `pop` while empty
+struct BoundedQueue<T> {
+    items: Mutex<Deque<T>>,
+    capacity: usize,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> BoundedQueue<T> {
+    fn new(capacity: usize) -> Self {
+        BoundedQueue {
+            items: Mutex::new(Deque::with_capacity(capacity)),
+            capacity,
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    fn push(&self, value: T) {
+        let mut items = self
+            .not_full
+            .wait_while(self.items.lock().unwrap(), |items| {
+                items.len() == self.capacity
+            })
+            .unwrap();
+        items.push_back(value);
+        drop(items);
+        self.not_empty.notify_one();
+    }
+
+    fn pop(&self) -> T {
+        let mut items = self
+            .not_empty
+            .wait_while(self.items.lock().unwrap(), |items| items.is_empty())
+            .unwrap();
+        let value = items.pop_front().unwrap();
+        drop(items);
+        self.not_full.notify_one();
+        value
+    }
+
+    /// `pop` that gives up after timeout
+    fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let (mut items, _) = self
+            .not_empty
+            .wait_timeout_while(self.items.lock().unwrap(), timeout, |items| {
+                items.is_empty()
+            })
+            .unwrap();
+        let value = items.pop_front();
+        drop(items);
+        if value.is_some() {
+            self.not_full.notify_one();
+        }
+        value
+    }
+
+    fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+}
+
+#[test]
+fn bounded_queue_producers_consumers() {
+    const PRODUCERS: usize = 4;
+    const CONSUMERS: usize = 3;
+    const PER_PRODUCER: usize = 2000;
+
+    // small enough that producers block on a full queue all the time
+    let queue = Arc::new(BoundedQueue::new(4));
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|p| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    queue.push(Some(p * PER_PRODUCER + i));
+                    assert!(queue.len() <= 4);
+                }
+            })
+        })
+        .collect();
+
+    let consumers: Vec<_> = (0..CONSUMERS)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                // None marks the end of the stream
+                while let Some(value) = queue.pop() {
+                    seen.push(value);
+                }
+                seen
+            })
+        })
+        .collect();
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    for _ in 0..CONSUMERS {
+        queue.push(None);
+    }
+
+    let mut seen: Vec<_> = consumers
+        .into_iter()
+        .flat_map(|consumer| consumer.join().unwrap())
+        .collect();
+    seen.sort_unstable();
+    assert_eq!(seen, (0..PRODUCERS * PER_PRODUCER).collect::<Vec<_>>());
+    assert_eq!(queue.len(), 0);
+}
+
+#[test]
+fn bounded_queue_blocks_until_space() {
+    let queue = Arc::new(BoundedQueue::new(1));
+    queue.push(1);
+
+    let pusher = thread::spawn({
+        let queue = queue.clone();
+        move || queue.push(2)
+    });
+
+    // the pusher can't get in until we take the first item out
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.pop(), 1);
+    pusher.join().unwrap();
+    assert_eq!(queue.pop(), 2);
+
+    assert_eq!(queue.pop_timeout(Duration::from_millis(10)), None);
+}